@@ -1,14 +1,396 @@
+use crate::output::OutputFormat;
+use crate::sink::Sink;
+use anyhow::{anyhow, bail};
+use std::{
+  collections::HashMap,
+  env, fs,
+  io::{self, IsTerminal},
+  path::PathBuf,
+};
+
+#[derive(Clone, PartialEq)]
+pub enum Command {
+  /// Reads the input, passes it through and sends the backtraces to neovim
+  Pipe,
+  /// Markdown bug report of the last backtrace in the input
+  Report,
+  /// Backtraces stored by earlier runs
+  History(HistoryCommand),
+  /// Where two backtraces, history ids or JSON files, part ways
+  Diff(String, String),
+  /// Language server the other stacky processes send the backtraces to
+  Lsp,
+  /// Delivers the backtraces of the other stacky processes
+  Daemon,
+  /// Lets the `.stacky` file of the project run commands and write files
+  Trust,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum HistoryCommand {
+  List,
+  Show(u64),
+  /// Sends a stored backtrace to neovim again
+  Resend(u64),
+}
+
+impl HistoryCommand {
+  fn parse(
+    command: Option<String>,
+    id: impl FnOnce() -> anyhow::Result<String>,
+  ) -> anyhow::Result<Self> {
+    match command.as_deref() {
+      None | Some("list") => Ok(Self::List),
+      Some("show") => Ok(Self::Show(id()?.parse()?)),
+      Some("resend") => Ok(Self::Resend(id()?.parse()?)),
+      Some(command) => {
+        bail!("`history` is list, show <id> or resend <id>, not `{command}`")
+      }
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ColorMode {
+  Always,
+  Never,
+  /// Colors when stdout is a terminal and `NO_COLOR` isn't set
+  Auto,
+}
+
+impl ColorMode {
+  fn parse(mode: &str) -> anyhow::Result<Self> {
+    match mode {
+      "always" => Ok(Self::Always),
+      "never" => Ok(Self::Never),
+      "auto" => Ok(Self::Auto),
+      _ => bail!("`--color` is always, never or auto, not `{mode}`"),
+    }
+  }
+
+  pub fn enabled(self) -> bool {
+    match self {
+      Self::Always => true,
+      Self::Never => false,
+      Self::Auto => {
+        io::stdout().is_terminal()
+          && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+      }
+    }
+  }
+}
+
 #[derive(Clone)]
 pub struct Config {
+  pub command: Command,
   pub verbose: bool,
+  /// Gets the backtrace object, `{"message": ..., "entries": [...]}`
+  pub backtrace_function: String,
+  /// Older plugins without `backtrace_function` get the bare list of entries
   pub stacky_function: String,
+  pub diagnostics_function: String,
+  /// Frames kept from a single backtrace, the middle of longer ones (stack
+  /// overflows) is elided. `--max-frames 0` or `none` keeps them all
+  pub max_frames: Option<usize>,
+  /// Sends the demangled symbols with their hashes along with the names
+  pub keep_hash: bool,
+  /// Runs of tokio, futures and other async runtime frames become a marker
+  pub collapse_runtime: bool,
+  /// Source lines shown above and below the location of a frame, 0 for none
+  pub context_lines: usize,
+  pub color: ColorMode,
+  /// Locations are OSC 8 hyperlinks to this URI, `{path}`, `{line}` and
+  /// `{column}` are filled in
+  pub editor_uri: String,
+  pub format: OutputFormat,
+  /// SARIF log of all the backtraces, written when the input ends
+  pub sarif: Option<PathBuf>,
+  /// HTML report of all the backtraces, written when the input ends
+  pub html: Option<PathBuf>,
+  /// Binary whose DWARF resolves frames that only have an address, alone it
+  /// only works for binaries that aren't position independent
+  pub executable: Option<PathBuf>,
+  /// Copy of `/proc/<pid>/maps` with the load addresses of the binaries, it
+  /// has to be taken by hand while the program runs, stacky can't see it
+  pub maps: Option<PathBuf>,
+  /// Stores every backtrace under `$XDG_STATE_HOME/stacky`
+  pub history: bool,
+  /// Backtraces kept in the history
+  pub history_limit: usize,
+  /// Days the history keeps a backtrace for
+  pub history_days: Option<u64>,
+  /// What the input came from, stored in the history
+  pub command_label: Option<String>,
+  /// Seconds a repeat of the same backtrace is only counted for, 0 to send
+  /// every one of them
+  pub dedup_window: u64,
+  /// Browse the backtraces in the terminal once the input ends
+  pub tui: bool,
+  /// Where the backtraces and diagnostics go, neovim by default
+  pub sinks: Vec<Sink>,
+  /// Kakoune session for the `kak` sink, `$kak_session` by default
+  pub kak_session: String,
+  /// Client of the session that opens the file, `$kak_client` or `client0`
+  pub kak_client: String,
 }
 
 impl Default for Config {
   fn default() -> Self {
     Self {
+      command: Command::Pipe,
       verbose: false,
+      backtrace_function: "stacky_backtrace_global".to_string(),
       stacky_function: "stacky_global".to_string(),
+      diagnostics_function: "stacky_diagnostics_global".to_string(),
+      max_frames: Some(200),
+      keep_hash: false,
+      collapse_runtime: true,
+      context_lines: 2,
+      color: ColorMode::Auto,
+      editor_uri: "file://{path}".to_string(),
+      format: OutputFormat::Pretty,
+      sarif: None,
+      html: None,
+      executable: None,
+      maps: None,
+      history: true,
+      history_limit: 100,
+      history_days: Some(30),
+      command_label: None,
+      dedup_window: 60,
+      tui: false,
+      sinks: vec![Sink::Nvim],
+      kak_session: env::var("kak_session").unwrap_or_default(),
+      kak_client: env::var("kak_client")
+        .unwrap_or_else(|_| "client0".to_string()),
+    }
+  }
+}
+
+impl Config {
+  /// `stacky [report] [--verbose] [--keep-hash] [--no-collapse] [--context <lines>]
+  /// [--max-frames <count>] [--color <when>] [--editor-uri <template>]
+  /// [--format <format>]
+  /// [--sarif <path>] [--html <path>] [--exe <path>] [--maps <path>]
+  /// [--no-history] [--history-limit <count>] [--history-days <days>]
+  /// [--command <command>] [--dedup-window <seconds>] [--tui]
+  /// [--sink <editor or template>]... [--kak-session <session>]
+  /// [--kak-client <client>]`,
+  /// `stacky history [list | show <id> [--tui] | resend <id>]`,
+  /// `stacky diff <a> <b>`, `stacky lsp`, `stacky daemon` or `stacky trust`,
+  /// values can also be given as `--exe=<path>`
+  pub fn from_args(args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+    let mut args = args.peekable();
+    let mut config = Self::default();
+    let mut sinks = Vec::new();
+
+    while let Some(arg) = args.next() {
+      let (name, value) = match arg.split_once('=') {
+        Some((name, value)) => (name, Some(value.to_string())),
+        None => (arg.as_str(), None),
+      };
+      let mut value = || {
+        value
+          .clone()
+          .or_else(|| args.next())
+          .ok_or_else(|| anyhow!("`{name}` needs a value"))
+      };
+
+      match name {
+        "report" => config.command = Command::Report,
+        "history" => {
+          // `stacky history -v` lists them
+          let command = args.next_if(|arg| !arg.starts_with('-'));
+          let history = HistoryCommand::parse(command, || {
+            args.next().ok_or_else(|| anyhow!("`history` needs an id"))
+          })?;
+          config.command = Command::History(history);
+        }
+        "lsp" => config.command = Command::Lsp,
+        "daemon" => config.command = Command::Daemon,
+        "trust" => config.command = Command::Trust,
+        "diff" => {
+          let mut backtrace = || {
+            args
+              .next()
+              .ok_or_else(|| anyhow!("`diff` needs two backtraces"))
+          };
+          config.command = Command::Diff(backtrace()?, backtrace()?);
+        }
+        "-v" | "--verbose" => config.verbose = true,
+        "--keep-hash" => config.keep_hash = true,
+        "--no-collapse" => config.collapse_runtime = false,
+        "--context" => config.context_lines = value()?.parse()?,
+        "--max-frames" => {
+          config.max_frames = match value()?.as_str() {
+            "0" | "none" => None,
+            frames => Some(frames.parse()?),
+          }
+        }
+        "--color" => config.color = ColorMode::parse(&value()?)?,
+        "--editor-uri" => config.editor_uri = value()?,
+        "--format" => config.format = OutputFormat::parse(&value()?)?,
+        "--sarif" => config.sarif = Some(value()?.into()),
+        "--html" => config.html = Some(value()?.into()),
+        "--exe" => config.executable = Some(value()?.into()),
+        "--maps" => config.maps = Some(value()?.into()),
+        "--no-history" => config.history = false,
+        "--history-limit" => config.history_limit = value()?.parse()?,
+        "--history-days" => config.history_days = Some(value()?.parse()?),
+        "--command" => config.command_label = Some(value()?),
+        "--dedup-window" => config.dedup_window = value()?.parse()?,
+        "--tui" => config.tui = true,
+        "--sink" => sinks.push(Sink::parse(&value()?)),
+        "--kak-session" => config.kak_session = value()?,
+        "--kak-client" => config.kak_client = value()?,
+        _ => bail!("unknown argument `{arg}`"),
+      }
+    }
+
+    if !sinks.is_empty() {
+      config.sinks = sinks;
+    }
+    if config.sinks.contains(&Sink::Kak) && config.kak_session.is_empty() {
+      eprintln!(
+        "Stacky: no kakoune session, give `--kak-session` or run stacky \
+         from kakoune, the kak sink is skipped"
+      );
+      config.sinks.retain(|sink| *sink != Sink::Kak);
     }
+    Ok(config)
   }
+
+  /// Arguments in the `.stacky` file of the project, one per line, they go
+  /// before the ones on the command line. Until `stacky trust` the file
+  /// can't run commands or write files
+  pub fn project_args() -> Vec<String> {
+    let Some((path, file)) = project_file() else {
+      return Vec::new();
+    };
+    let args = file
+      .lines()
+      .map(str::trim)
+      .filter(|line| !line.is_empty() && !line.starts_with('#'))
+      .map(str::to_string)
+      .collect();
+    if trusted().get(&path) == Some(&file) {
+      return args;
+    }
+
+    let (args, refused) = untrusted_args(args);
+    if !refused.is_empty() {
+      eprintln!(
+        "Stacky: ignoring `{}` from {}, run `stacky trust` to allow it",
+        refused.join(" "),
+        path.display()
+      );
+    }
+    args
+  }
+
+  /// Trusts the `.stacky` file as it is now, it has to be trusted again
+  /// once it changes
+  pub fn trust() -> anyhow::Result<PathBuf> {
+    let (path, file) =
+      project_file().ok_or_else(|| anyhow!("no .stacky file to trust"))?;
+    let mut trusted = trusted();
+    trusted.insert(path.clone(), file);
+
+    let store = trust_store()?;
+    if let Some(dir) = store.parent() {
+      fs::create_dir_all(dir)?;
+    }
+    fs::write(&store, serde_json::to_string_pretty(&trusted)?)?;
+    Ok(path)
+  }
+
+  /// stdout is for JSON or the report, everything else goes to stderr
+  pub fn reserves_stdout(&self) -> bool {
+    self.format.is_json() || self.command == Command::Report
+  }
+}
+
+/// The nearest `.stacky` file and its contents
+fn project_file() -> Option<(PathBuf, String)> {
+  let cwd = env::current_dir().ok()?;
+  cwd.ancestors().find_map(|dir| {
+    let path = dir.join(".stacky");
+    Some((path.clone(), fs::read_to_string(path).ok()?))
+  })
+}
+
+/// `$XDG_CONFIG_HOME/stacky/trusted.json`, the trusted `.stacky` files with
+/// their contents
+fn trust_store() -> anyhow::Result<PathBuf> {
+  let config = env::var_os("XDG_CONFIG_HOME")
+    .filter(|dir| !dir.is_empty())
+    .map(PathBuf::from)
+    .or_else(|| {
+      env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+    })
+    .ok_or_else(|| anyhow!("neither XDG_CONFIG_HOME nor HOME is set"))?;
+  Ok(config.join("stacky/trusted.json"))
+}
+
+fn trusted() -> HashMap<PathBuf, String> {
+  trust_store()
+    .ok()
+    .and_then(|store| fs::read_to_string(store).ok())
+    .and_then(|json| serde_json::from_str(&json).ok())
+    .unwrap_or_default()
+}
+
+/// Leaves out the sink templates, `--html`, `--sarif` and `trust`, and
+/// returns them apart
+fn untrusted_args(args: Vec<String>) -> (Vec<String>, Vec<String>) {
+  let mut kept = Vec::new();
+  let mut refused = Vec::new();
+  let mut args = args.into_iter();
+
+  while let Some(arg) = args.next() {
+    let name = arg.split_once('=').map_or(arg.as_str(), |(name, _)| name);
+    // `trust` would trust the file itself
+    if name == "trust" {
+      refused.push(arg);
+      continue;
+    }
+    if !matches!(name, "--sink" | "--html" | "--sarif") {
+      kept.push(arg);
+      continue;
+    }
+
+    let mut pair = vec![arg.clone()];
+    let value = match arg.split_once('=') {
+      Some((_, value)) => Some(value.to_string()),
+      None => args.next().inspect(|value| pair.push(value.clone())),
+    };
+    let builtin = value.as_deref().and_then(Sink::builtin).is_some();
+    match name == "--sink" && builtin {
+      true => kept.extend(pair),
+      false => refused.extend(pair),
+    }
+  }
+  (kept, refused)
+}
+
+#[test]
+fn untrusted_args_test() {
+  let args = [
+    "--sink",
+    "kak",
+    "--sink=code",
+    "--sink",
+    "curl {message}",
+    "--html",
+    "/tmp/x.html",
+    "--context",
+    "4",
+  ];
+  let (kept, refused) =
+    untrusted_args(args.iter().map(|arg| arg.to_string()).collect());
+  assert_eq!(kept, ["--sink", "kak", "--sink=code", "--context", "4"]);
+  assert_eq!(
+    refused,
+    ["--sink", "curl {message}", "--html", "/tmp/x.html"]
+  );
 }
@@ -0,0 +1,177 @@
+//! What is printed before a backtrace: the test it belongs to and the panic
+//!
+//! ---- tests::foo stdout ----
+//! thread 'tests::foo' panicked at src/lib.rs:10:9:
+//! assertion `left == right` failed
+//!   left: 1
+//!  right: 2
+use crate::types::{Assertion, Backtrace};
+use nom::{
+  bytes::complete::{tag, take_until},
+  combinator::rest,
+  sequence::tuple,
+  IResult,
+};
+
+#[derive(Default)]
+pub struct Context {
+  test: Option<String>,
+  thread: Option<String>,
+  message: Vec<String>,
+  left: Option<String>,
+  right: Option<String>,
+  /// Lines following the panic header are its message
+  in_panic: bool,
+}
+
+impl Context {
+  /// Feeds a line that isn't a part of any backtrace
+  pub fn push_line(&mut self, line: &str) {
+    if let Some(test) = parse_test_header(line) {
+      *self = Self {
+        test: Some(test.to_string()),
+        ..Self::default()
+      };
+      return;
+    }
+
+    if let Ok((_, (thread, rest))) = parse_panic(line) {
+      self.clear_panic();
+      self.thread = Some(thread.to_string());
+      self.in_panic = true;
+
+      // Before 1.73 the message was quoted on the same line
+      if let Some((message, _location)) = rest.rsplit_once("', ") {
+        self
+          .message
+          .push(message.trim_start_matches('\'').to_string());
+      }
+      return;
+    }
+
+    if !self.in_panic {
+      return;
+    }
+
+    let trimmed = line.trim_start();
+    if trimmed.starts_with("note: ") {
+      self.in_panic = false;
+    } else if let Some(left) = trimmed.strip_prefix("left: ") {
+      self.left = Some(unquote(left));
+    } else if let Some(right) = trimmed.strip_prefix("right: ") {
+      self.right = Some(unquote(right));
+    } else {
+      self.message.push(line.to_string());
+    }
+  }
+
+  /// Tags the backtrace with the panic and the test, the panic is used up
+  /// but the test lasts until the next test header
+  pub fn apply(&mut self, mut backtrace: Backtrace) -> Backtrace {
+    if let Some(test) = &self.test {
+      backtrace = backtrace.with_test(test.clone());
+    }
+    if let Some(thread) = self.thread.take() {
+      backtrace = backtrace.with_thread(thread);
+    }
+    if !self.message.is_empty() {
+      backtrace = backtrace.with_message(self.message.join("\n"));
+    }
+    if let (Some(left), Some(right)) = (self.left.take(), self.right.take()) {
+      backtrace = backtrace.with_assertion(Assertion { left, right });
+    }
+
+    self.clear_panic();
+    backtrace
+  }
+
+  fn clear_panic(&mut self) {
+    self.thread = None;
+    self.message.clear();
+    self.left = None;
+    self.right = None;
+    self.in_panic = false;
+  }
+}
+
+/// `cargo test` prints `---- tests::foo stdout ----`, nextest
+/// `--- STDERR: crate tests::foo ---` or `──── STDERR: crate tests::foo`
+fn parse_test_header(line: &str) -> Option<&str> {
+  let line = line.trim();
+
+  if let Some(header) = line.strip_prefix("---- ") {
+    return header
+      .strip_suffix(" stdout ----")
+      .or_else(|| header.strip_suffix(" stderr ----"));
+  }
+
+  let header = line.trim_start_matches(['-', '─']);
+  if header.len() == line.len() {
+    return None;
+  }
+
+  let header = header.trim_start();
+  let name = header
+    .strip_prefix("STDERR:")
+    .or_else(|| header.strip_prefix("STDOUT:"))?
+    .trim_end_matches(['-', '─']);
+
+  // The binary id goes before the test name
+  name.split_whitespace().last()
+}
+
+/// thread 'tests::foo' panicked at src/lib.rs:10:9:
+fn parse_panic(i: &str) -> IResult<&str, (&str, &str)> {
+  let (i, (_, thread, _, _, rest)) = tuple((
+    tag("thread '"),
+    take_until("'"),
+    take_until(" panicked at "),
+    tag(" panicked at "),
+    rest,
+  ))(i)?;
+  Ok((i, (thread, rest)))
+}
+
+/// Older `assert_eq!` quoted the values: left: `1`,
+fn unquote(value: &str) -> String {
+  value.trim_end_matches(',').trim_matches('`').to_string()
+}
+
+#[test]
+fn test_header_parser_test() {
+  let header = parse_test_header("---- tests::foo stdout ----");
+  assert_eq!(header, Some("tests::foo"));
+
+  let header = parse_test_header("--- STDERR:  crate::tests::foo ---");
+  assert_eq!(header, Some("crate::tests::foo"));
+
+  let header = parse_test_header("──── STDERR:   myapp tests::foo");
+  assert_eq!(header, Some("tests::foo"));
+
+  assert_eq!(parse_test_header("failures:"), None);
+}
+
+#[test]
+fn context_test() {
+  use crate::{format::BacktraceFormat, rust::Rust};
+
+  let input = include_str!("./tests/fixtures/cargo_test.txt");
+  let mut context = Context::default();
+  let mut backtraces = Vec::new();
+
+  for line in input.lines() {
+    if line == "stack backtrace:" {
+      backtraces.push(context.apply(Backtrace::default()));
+    } else if !Rust::default().is_continuation(line) {
+      context.push_line(line);
+    }
+  }
+
+  assert_eq!(backtraces.len(), 2);
+  assert_eq!(backtraces[0].test(), Some("tests::bar"));
+  assert!(backtraces[0].assertion().is_none());
+
+  assert_eq!(backtraces[1].test(), Some("tests::foo"));
+  let assertion = backtraces[1].assertion().unwrap();
+  assert_eq!(assertion.to_string(), "left=1 right=2");
+}
@@ -0,0 +1,268 @@
+//! `stacky daemon`, one process delivering for every piped stacky, it owns
+//! the editor connections, the history and the repeat counters
+//!
+//! The piped processes send what they parsed through a socket in
+//! `$XDG_RUNTIME_DIR/stacky`, one JSON `Message` per line. The daemon first
+//! tells them its sinks, they deliver to the others themselves. Without a
+//! daemon they deliver everything themselves.
+use crate::config::Config;
+use crate::history::Origin;
+use crate::sink::Sink;
+use crate::types::{Backtrace, Diagnostic, Report};
+use crate::utils::{get_nvim_pipes, runtime_dir};
+use anyhow::bail;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+  collections::{HashMap, HashSet, VecDeque},
+  fs,
+  io::{self, BufRead, BufReader, Write},
+  os::unix::net::{UnixListener, UnixStream},
+  path::PathBuf,
+  thread,
+  time::{Duration, Instant},
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+/// Reports a neovim started later gets, the oldest go first
+const LATE: usize = 10;
+
+/// The daemon answers right away, a socket that doesn't isn't one
+const HELLO: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Message {
+  /// Unfiltered, with the paths as they were printed. The daemon stores it
+  /// and counts its repeats before filtering it
+  Backtrace {
+    origin: Origin,
+    raw: String,
+    backtrace: Box<Backtrace>,
+    /// `false` with `--no-history`
+    history: bool,
+  },
+  /// Repeats of a backtrace sent before, counted by the producer
+  Repeat { fingerprint: String, count: usize },
+  /// Resolved already
+  Diagnostics(Vec<Diagnostic>),
+}
+
+fn socket() -> io::Result<PathBuf> {
+  Ok(runtime_dir()?.join("daemon"))
+}
+
+/// Connection of a piped stacky to the daemon
+pub struct Producer {
+  stream: UnixStream,
+  origin: Origin,
+  history: bool,
+  /// Where the daemon delivers to
+  sinks: Vec<Sink>,
+  /// Repeats the daemon was told about, it gets the new ones
+  counted: HashMap<String, usize>,
+}
+
+impl Producer {
+  /// `None` when no daemon is running
+  pub fn connect(config: &Config) -> Option<Self> {
+    let stream = UnixStream::connect(socket().ok()?).ok()?;
+    stream.set_read_timeout(Some(HELLO)).ok()?;
+    let mut sinks = String::new();
+    BufReader::new(&stream).read_line(&mut sinks).ok()?;
+    stream.set_read_timeout(None).ok()?;
+
+    Some(Self {
+      stream,
+      origin: Origin::current(config),
+      history: config.history,
+      sinks: serde_json::from_str(&sinks).ok()?,
+      counted: HashMap::new(),
+    })
+  }
+
+  pub fn sinks(&self) -> &[Sink] {
+    &self.sinks
+  }
+
+  /// The first of its repeats, the only one sent with the raw text
+  pub fn backtrace(
+    &mut self,
+    fingerprint: &str,
+    backtrace: &Backtrace,
+    raw: &str,
+  ) -> Message {
+    self.counted.insert(fingerprint.to_string(), 1);
+    Message::Backtrace {
+      origin: self.origin.clone(),
+      raw: raw.to_string(),
+      backtrace: Box::new(backtrace.clone()),
+      history: self.history,
+    }
+  }
+
+  /// The repeats since the last counter, from a counter of the producer
+  pub fn repeat(&mut self, backtrace: &mut Backtrace) -> Message {
+    let fingerprint = backtrace.fingerprint().to_string();
+    let count = backtrace.count();
+    let counted = self.counted.insert(fingerprint.clone(), count);
+    Message::Repeat {
+      fingerprint,
+      count: count.saturating_sub(counted.unwrap_or(1)),
+    }
+  }
+
+  pub fn send(&self, message: &Message) -> anyhow::Result<()> {
+    let json = serde_json::to_string(message)?;
+    writeln!(&self.stream, "{}", json)?;
+    Ok(())
+  }
+}
+
+/// Binds the socket, every producer is told the sinks and the messages of
+/// all of them come out of the receiver
+pub fn listen(sinks: &[Sink]) -> anyhow::Result<UnboundedReceiver<Message>> {
+  let socket = socket()?;
+  if UnixStream::connect(&socket).is_ok() {
+    bail!("a daemon is listening on {} already", socket.display());
+  }
+  // Left behind by a daemon that was killed
+  let _ = fs::remove_file(&socket);
+  let listener = UnixListener::bind(&socket)?;
+  let hello = serde_json::to_string(sinks)?;
+
+  let (messages, received) = unbounded_channel();
+  thread::spawn(move || {
+    for stream in listener.incoming().filter_map(Result::ok) {
+      if writeln!(&stream, "{}", hello).is_err() {
+        continue;
+      }
+      let messages = messages.clone();
+      // A producer stays connected until its input ends
+      thread::spawn(move || {
+        let lines = BufReader::new(stream).lines().map_while(Result::ok);
+        for message in lines.filter_map(|line| serde_json::from_str(&line).ok())
+        {
+          if messages.send(message).is_err() {
+            return;
+          }
+        }
+      });
+    }
+  });
+  Ok(received)
+}
+
+/// The last reports, for the neovim instances started after they were
+/// delivered
+pub struct Late {
+  /// Older reports are dropped, the dedup window
+  max_age: Duration,
+  reports: VecDeque<(Instant, Report)>,
+  pipes: HashSet<PathBuf>,
+  /// Started since the last check, they get a `THROTTLE` to load the plugin
+  pending: HashSet<PathBuf>,
+}
+
+impl Late {
+  pub fn new(max_age: Duration) -> Self {
+    Self {
+      max_age,
+      reports: VecDeque::new(),
+      pipes: HashSet::new(),
+      pending: HashSet::new(),
+    }
+  }
+
+  /// A new counter replaces the backtrace it counts
+  pub fn push(&mut self, mut report: Report, now: Instant) {
+    if let Report::Backtrace(backtrace) = &mut report {
+      let fingerprint = backtrace.fingerprint().to_string();
+      self.reports.retain_mut(|(_, report)| match report {
+        Report::Backtrace(old) => old.fingerprint() != fingerprint,
+        Report::Diagnostics(_) => true,
+      });
+    }
+    if self.reports.len() == LATE {
+      self.reports.pop_front();
+    }
+    self.reports.push_back((now, report));
+  }
+
+  /// The ones delivered within `max_age`
+  pub fn reports(&mut self, now: Instant) -> impl Iterator<Item = &Report> {
+    let max_age = self.max_age;
+    self
+      .reports
+      .retain(|(delivered, _)| now.duration_since(*delivered) <= max_age);
+    self.reports.iter().map(|(_, report)| report)
+  }
+
+  /// Pipes of the neovim instances that started since the check before the
+  /// last one
+  pub fn started(&mut self) -> Vec<PathBuf> {
+    let pipes: HashSet<PathBuf> = get_nvim_pipes().into_iter().collect();
+    let started = self.pending.intersection(&pipes).cloned().collect();
+    self.pending = pipes.difference(&self.pipes).cloned().collect();
+    self.pipes = pipes;
+    started
+  }
+}
+
+#[test]
+fn daemon_test() {
+  use crate::types::{Entry, Location};
+
+  let location = Location {
+    path: "src/main.rs".to_string(),
+    line: 4,
+    column: 5,
+  };
+  let backtrace = Backtrace::with_entries(vec![Entry::new(
+    "app::run".to_string(),
+    Some(location),
+  )]);
+
+  let message = Message::Backtrace {
+    origin: Origin {
+      command: None,
+      cwd: "/home/user/myapp".to_string(),
+      head: None,
+    },
+    raw: "raw".to_string(),
+    backtrace: Box::new(backtrace.clone()),
+    history: true,
+  };
+  let json = serde_json::to_string(&message).unwrap();
+  assert!(json.starts_with("{\"backtrace\":{\"origin\":"));
+  let Ok(Message::Backtrace {
+    backtrace: sent, ..
+  }) = serde_json::from_str(&json)
+  else {
+    panic!("a backtrace message");
+  };
+  assert_eq!(*sent, backtrace);
+
+  let mut late = Late::new(Duration::from_secs(60));
+  let start = Instant::now();
+  let mut counted = backtrace.clone();
+  counted.set_count(3);
+  late.push(Report::Backtrace(backtrace), start);
+  late.push(Report::Diagnostics(Vec::new()), start);
+  late.push(Report::Backtrace(counted), start);
+  let counts: Vec<usize> = late
+    .reports(start)
+    .map(|report| match report {
+      Report::Backtrace(backtrace) => backtrace.count(),
+      Report::Diagnostics(_) => 0,
+    })
+    .collect();
+  assert_eq!(counts, [0, 3]);
+
+  for _ in 0..LATE {
+    late.push(Report::Diagnostics(Vec::new()), start);
+  }
+  assert_eq!(late.reports(start).count(), LATE);
+  // A neovim started an hour later doesn't get them
+  let later = start + Duration::from_secs(3600);
+  assert_eq!(late.reports(later).count(), 0);
+}
@@ -0,0 +1,139 @@
+//! Panic storms: a backtrace that repeats within the window is only counted
+//!
+//! The first one goes out as usual, the editor gets the growing counter at
+//! most once per `THROTTLE`.
+use crate::types::Backtrace;
+use std::{
+  collections::HashMap,
+  time::{Duration, Instant},
+};
+
+/// How often the counter of a repeating backtrace is delivered
+pub const THROTTLE: Duration = Duration::from_secs(1);
+
+struct Seen {
+  /// Delivered with the latest count
+  backtrace: Backtrace,
+  count: usize,
+  /// Last time the backtrace happened, the window starts over from it
+  last: Instant,
+  sent: Instant,
+  sent_count: usize,
+}
+
+pub struct Dedup {
+  window: Duration,
+  seen: HashMap<String, Seen>,
+}
+
+impl Dedup {
+  pub fn new(window: Duration) -> Self {
+    Self {
+      window,
+      seen: HashMap::new(),
+    }
+  }
+
+  /// Counts the backtrace when it's a repeat, the new count is returned
+  pub fn repeat(&mut self, fingerprint: &str, now: Instant) -> Option<usize> {
+    self.repeat_by(fingerprint, 1, now)
+  }
+
+  /// Counts repeats another stacky process counted already
+  pub fn repeat_by(
+    &mut self,
+    fingerprint: &str,
+    times: usize,
+    now: Instant,
+  ) -> Option<usize> {
+    let seen = self.seen.get_mut(fingerprint)?;
+    if now.duration_since(seen.last) > self.window {
+      return None;
+    }
+
+    seen.count += times;
+    seen.last = now;
+    Some(seen.count)
+  }
+
+  /// Remembers a backtrace that was just delivered
+  pub fn insert(
+    &mut self,
+    fingerprint: String,
+    backtrace: Backtrace,
+    now: Instant,
+  ) {
+    let seen = Seen {
+      backtrace,
+      count: 1,
+      last: now,
+      sent: now,
+      sent_count: 1,
+    };
+    self.seen.insert(fingerprint, seen);
+  }
+
+  /// Backtraces whose counter changed since it was last delivered over
+  /// `THROTTLE` ago. The ones whose window is over are forgotten
+  pub fn due(&mut self, now: Instant) -> Vec<Backtrace> {
+    let window = self.window;
+    self.seen.retain(|_, seen| {
+      seen.count > seen.sent_count || now.duration_since(seen.last) <= window
+    });
+    self.take(|seen| now.duration_since(seen.sent) >= THROTTLE, now)
+  }
+
+  /// Every counter that wasn't delivered yet, for when the input ends
+  pub fn rest(&mut self) -> Vec<Backtrace> {
+    self.take(|_| true, Instant::now())
+  }
+
+  fn take(
+    &mut self,
+    ready: impl Fn(&Seen) -> bool,
+    now: Instant,
+  ) -> Vec<Backtrace> {
+    self
+      .seen
+      .values_mut()
+      .filter(|seen| seen.count > seen.sent_count && ready(seen))
+      .map(|seen| {
+        seen.sent = now;
+        seen.sent_count = seen.count;
+        seen.backtrace.set_count(seen.count);
+        seen.backtrace.clone()
+      })
+      .collect()
+  }
+}
+
+#[test]
+fn dedup_test() {
+  use crate::types::Entry;
+
+  let mut backtrace =
+    Backtrace::with_entries(vec![Entry::new("app::handler".to_string(), None)]);
+  let fingerprint = backtrace.fingerprint().to_string();
+
+  let mut dedup = Dedup::new(Duration::from_secs(60));
+  let start = Instant::now();
+  assert_eq!(dedup.repeat(&fingerprint, start), None);
+  dedup.insert(fingerprint.clone(), backtrace, start);
+
+  assert_eq!(dedup.repeat(&fingerprint, start), Some(2));
+  assert_eq!(dedup.repeat(&fingerprint, start), Some(3));
+  assert!(dedup.due(start).is_empty());
+
+  let later = start + THROTTLE;
+  let due = dedup.due(later);
+  assert_eq!(due.len(), 1);
+  assert_eq!(due[0].count(), 3);
+  assert!(dedup.due(later + THROTTLE).is_empty());
+
+  assert_eq!(dedup.repeat(&fingerprint, later), Some(4));
+  assert_eq!(dedup.rest()[0].count(), 4);
+
+  // Another storm once the window is over
+  let expired = later + Duration::from_secs(61);
+  assert_eq!(dedup.repeat(&fingerprint, expired), None);
+}
@@ -0,0 +1,226 @@
+//! `stacky diff`, where two backtraces part ways
+//!
+//! The frames are aligned by function, the outermost ones both backtraces
+//! share come last and frames of the same function at another line are marked.
+use crate::history::{History, Record};
+use crate::types::{Backtrace, Entry};
+use crate::utils::color;
+use anyhow::{anyhow, bail, Context};
+use serde_json::Value;
+use std::{fmt, fs, path::Path};
+
+pub struct Input {
+  label: String,
+  backtrace: Backtrace,
+}
+
+/// A JSON file, either a history record or what `--format json` prints, or
+/// the id of a backtrace in the history
+pub fn load(arg: &str, history: Option<&History>) -> anyhow::Result<Input> {
+  if Path::new(arg).is_file() {
+    let json = fs::read_to_string(arg)?;
+    let backtrace = parse(&json).with_context(|| format!("reading {}", arg))?;
+    return Ok(Input {
+      label: arg.to_string(),
+      backtrace,
+    });
+  }
+
+  let Ok(id) = arg.parse::<u64>() else {
+    bail!("`{arg}` is neither a file nor a history id");
+  };
+  let history = history.ok_or_else(|| anyhow!("no history to load {id}"))?;
+  Ok(Input {
+    label: format!("#{}", id),
+    backtrace: history.load(id)?.backtrace,
+  })
+}
+
+/// The last backtrace when the file has several. `jsonl` is refused, its
+/// lines are frames and nothing tells where one backtrace ends
+fn parse(json: &str) -> anyhow::Result<Backtrace> {
+  let documents = serde_json::Deserializer::from_str(json).into_iter::<Value>();
+  let Some(document) = documents.last() else {
+    bail!("no backtrace in it");
+  };
+  let document = document?;
+
+  if document.get("function").is_some() {
+    bail!("it's `--format jsonl`, one frame per line, diff `--format json`");
+  }
+  if let Some(backtrace) = document.get("backtrace") {
+    has_entries(backtrace)?;
+    let mut backtrace = Record::from_json(&document.to_string())?.backtrace;
+    backtrace.filter();
+    return Ok(backtrace);
+  }
+  has_entries(&document)?;
+  Ok(serde_json::from_value(document)?)
+}
+
+/// Every field of a backtrace is optional, any JSON object would do
+fn has_entries(backtrace: &Value) -> anyhow::Result<()> {
+  match backtrace.get("entries") {
+    Some(Value::Array(_)) => Ok(()),
+    _ => bail!("not a backtrace, there's no `entries` list"),
+  }
+}
+
+#[derive(Debug, PartialEq)]
+enum Op<'a> {
+  Same(&'a Entry, &'a Entry),
+  Removed(&'a Entry),
+  Added(&'a Entry),
+}
+
+pub struct Diff<'a> {
+  a: &'a Input,
+  b: &'a Input,
+  ops: Vec<Op<'a>>,
+}
+
+impl<'a> Diff<'a> {
+  pub fn new(a: &'a Input, b: &'a Input) -> Self {
+    Self {
+      a,
+      b,
+      ops: align(a.backtrace.entries(), b.backtrace.entries()),
+    }
+  }
+
+  /// Frames both backtraces end with
+  fn shared(&self) -> usize {
+    let ops = self.ops.iter().rev();
+    ops.take_while(|op| matches!(op, Op::Same(..))).count()
+  }
+}
+
+/// Longest common subsequence of the functions
+fn align<'a>(a: &'a [Entry], b: &'a [Entry]) -> Vec<Op<'a>> {
+  // lengths[i][j] is the LCS of a[i..] and b[j..]
+  let mut lengths = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+  for i in (0..a.len()).rev() {
+    for j in (0..b.len()).rev() {
+      lengths[i][j] = match a[i].same_frame(&b[j]) {
+        true => lengths[i + 1][j + 1] + 1,
+        false => lengths[i + 1][j].max(lengths[i][j + 1]),
+      };
+    }
+  }
+
+  let mut ops = Vec::with_capacity(a.len().max(b.len()));
+  let (mut i, mut j) = (0, 0);
+  while i < a.len() || j < b.len() {
+    if i < a.len() && j < b.len() && a[i].same_frame(&b[j]) {
+      ops.push(Op::Same(&a[i], &b[j]));
+      i += 1;
+      j += 1;
+    } else if j == b.len()
+      || (i < a.len() && lengths[i + 1][j] >= lengths[i][j + 1])
+    {
+      ops.push(Op::Removed(&a[i]));
+      i += 1;
+    } else {
+      ops.push(Op::Added(&b[j]));
+      j += 1;
+    }
+  }
+  ops
+}
+
+fn header(f: &mut fmt::Formatter, sign: &str, input: &Input) -> fmt::Result {
+  let message = input.backtrace.message().and_then(|m| m.lines().next());
+  color(f, 1, 0, &format!("{} {}", sign, input.label));
+  writeln!(f, " {}", message.unwrap_or(""))
+}
+
+impl fmt::Display for Diff<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    header(f, "---", self.a)?;
+    header(f, "+++", self.b)?;
+
+    let shared = self.shared();
+    if shared == self.ops.len() {
+      color(f, 8, 0, "… the backtraces have the same frames …\n");
+    }
+
+    for (index, op) in self.ops.iter().enumerate() {
+      if index == self.ops.len() - shared && index > 0 {
+        let marker = format!("… {} outermost frames are shared …\n", shared);
+        color(f, 8, 0, &marker);
+      }
+
+      match op {
+        Op::Removed(entry) => {
+          color(f, 1, 0, "- ");
+          writeln!(f, "{}", entry)?;
+        }
+        Op::Added(entry) => {
+          color(f, 2, 0, "+ ");
+          writeln!(f, "{}", entry)?;
+        }
+        Op::Same(a, b) if a.moved(b) => {
+          color(f, 3, 0, "~ ");
+          color(f, 4, 0, a.function());
+          let (Some(from), Some(to)) = (a.location(), b.location()) else {
+            unreachable!("frames without a location don't move");
+          };
+          writeln!(f, " {} → {}", from, to)?;
+        }
+        Op::Same(a, _) => writeln!(f, "  {}", a)?,
+      }
+    }
+    Ok(())
+  }
+}
+
+#[test]
+fn diff_test() {
+  use crate::types::Location;
+
+  let entry = |function: &str, line| {
+    let location = Location {
+      path: "src/main.rs".to_string(),
+      line,
+      column: 5,
+    };
+    Entry::new(function.to_string(), Some(location))
+  };
+  let a = Backtrace::with_entries(vec![
+    entry("app::parse", 10),
+    entry("app::run", 20),
+    entry("app::main", 30),
+  ]);
+  let b = Backtrace::with_entries(vec![
+    entry("app::validate", 12),
+    entry("app::run", 24),
+    entry("app::main", 30),
+  ]);
+
+  let json = serde_json::to_string(&b).unwrap();
+  let b = parse(&json).unwrap();
+  assert!(parse(r#"{"name": "app", "version": "1.0.0"}"#).is_err());
+  let jsonl = crate::output::render(&b, crate::output::OutputFormat::Jsonl);
+  assert!(parse(&jsonl).is_err());
+
+  let ops = align(a.entries(), b.entries());
+  assert_eq!(ops.len(), 4);
+  assert_eq!(ops[0], Op::Removed(&a.entries()[0]));
+  assert_eq!(ops[1], Op::Added(&b.entries()[0]));
+
+  let a = Input {
+    label: "a".to_string(),
+    backtrace: a,
+  };
+  let b = Input {
+    label: "b".to_string(),
+    backtrace: b,
+  };
+  let diff = Diff::new(&a, &b);
+  assert_eq!(diff.shared(), 2);
+
+  let rendered = crate::utils::strip_ansi(&diff.to_string());
+  assert!(rendered.contains("… 2 outermost frames are shared …"));
+  assert!(rendered.contains("~ app::run src/main.rs:20:5 → src/main.rs:24:5"));
+  assert!(rendered.contains("  app::main src/main.rs:30:5"));
+}
@@ -0,0 +1,234 @@
+//! `--sink emacs`, a `*stacky*` compilation-mode buffer in the emacs servers
+//! whose `default-directory` the report has a location in
+//!
+//! `next-error` walks the frames like it walks compiler errors.
+use crate::types::{Backtrace, Diagnostic, Report, Severity};
+use crate::utils::get_emacs_sockets;
+use std::{fmt::Write, path::Path, process::Command};
+
+/// `default-directory` of the buffer the user is in, `~` expanded
+const DEFAULT_DIRECTORY: &str = "(with-current-buffer \
+  (window-buffer (selected-window)) (expand-file-name default-directory))";
+
+pub fn deliver(report: &Report, verbose: bool) {
+  for socket in get_emacs_sockets() {
+    if verbose {
+      eprintln!("emacs socket: {:?}", socket);
+    }
+
+    let Some(directory) = eval(&socket, DEFAULT_DIRECTORY) else {
+      continue;
+    };
+    let directory = unquote(&directory);
+    if !report.has_location(&directory) {
+      continue;
+    }
+
+    let expression = match report {
+      // Counters only touch the header, the user's place in the frames stays
+      Report::Backtrace(backtrace) if backtrace.count() > 1 => {
+        count_elisp(backtrace)
+      }
+      report => elisp(report, &directory),
+    };
+    if eval(&socket, &expression).is_none() {
+      eprintln!("Stacky error sending backtrace to emacs '{:?}'", socket);
+    }
+  }
+}
+
+/// What emacsclient printed for the expression, `None` when it failed
+fn eval(socket: &Path, expression: &str) -> Option<String> {
+  let output = Command::new("emacsclient")
+    .arg("--socket-name")
+    .arg(socket)
+    .arg("--eval")
+    .arg(expression)
+    .output()
+    .ok()?;
+  output
+    .status
+    .success()
+    .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Fills the buffer and makes it the one `next-error` uses
+fn elisp(report: &Report, directory: &str) -> String {
+  let fingerprint = match report {
+    Report::Backtrace(backtrace) => backtrace.clone().fingerprint().to_string(),
+    Report::Diagnostics(_) => String::new(),
+  };
+  format!(
+    "(with-current-buffer (get-buffer-create \"*stacky*\")
+  (let ((inhibit-read-only t))
+    (erase-buffer)
+    (insert {}))
+  (setq default-directory {})
+  (compilation-mode)
+  (setq-local stacky-fingerprint {})
+  (goto-char (point-min))
+  (setq next-error-last-buffer (current-buffer))
+  (display-buffer (current-buffer))
+  nil)",
+    quote(&lines(report)),
+    quote(directory),
+    quote(&fingerprint)
+  )
+}
+
+/// Replaces the header when the buffer still shows the backtrace
+fn count_elisp(backtrace: &Backtrace) -> String {
+  format!(
+    "(let ((buffer (get-buffer \"*stacky*\")))
+  (when buffer
+    (with-current-buffer buffer
+      (when (equal (bound-and-true-p stacky-fingerprint) {})
+        (let ((inhibit-read-only t))
+          (save-excursion
+            (goto-char (point-min))
+            (delete-region (point) (line-end-position))
+            (insert {}))))))
+  nil)",
+    quote(backtrace.clone().fingerprint()),
+    quote(&header(backtrace))
+  )
+}
+
+/// A header and a `path:line:col: ` line for every location
+fn lines(report: &Report) -> String {
+  let mut lines = String::new();
+  match report {
+    Report::Backtrace(backtrace) => backtrace_lines(&mut lines, backtrace),
+    Report::Diagnostics(diagnostics) => {
+      lines.push_str("Compiler diagnostics\n\n");
+      for diagnostic in diagnostics {
+        diagnostic_lines(&mut lines, diagnostic);
+      }
+    }
+  }
+  lines
+}
+
+/// `Panic ×3 in thread 'main'`
+fn header(backtrace: &Backtrace) -> String {
+  let mut header = "Panic".to_string();
+  if backtrace.count() > 1 {
+    let _ = write!(header, " ×{}", backtrace.count());
+  }
+  if let Some(test) = backtrace.test() {
+    let _ = write!(header, " in {}", test);
+  }
+  if let Some(thread) = backtrace.thread() {
+    let _ = write!(header, " in thread '{}'", thread);
+  }
+  header
+}
+
+fn backtrace_lines(lines: &mut String, backtrace: &Backtrace) {
+  let _ = writeln!(lines, "{}", header(backtrace));
+  // Indented so compilation-mode doesn't take them for errors
+  for line in backtrace.message().into_iter().flat_map(str::lines) {
+    let _ = writeln!(lines, "  {}", line);
+  }
+  if let Some(assertion) = backtrace.assertion() {
+    let _ = writeln!(lines, "  {}", assertion);
+  }
+  lines.push('\n');
+
+  for entry in backtrace.entries() {
+    if let Some(location) = entry.location() {
+      let _ = writeln!(lines, "{}: {}", location, entry.function());
+    }
+  }
+  for label in backtrace.labels() {
+    let _ = writeln!(lines, "{}: {}", label.location, label.label);
+  }
+}
+
+fn diagnostic_lines(lines: &mut String, diagnostic: &Diagnostic) {
+  if let Some(location) = &diagnostic.location {
+    let severity = match diagnostic.severity {
+      Severity::Error => "error",
+      Severity::Warning => "warning",
+      Severity::Note => "note",
+      Severity::Help => "help",
+    };
+    let message = diagnostic.message.lines().next().unwrap_or("");
+    let _ = writeln!(lines, "{}: {}: {}", location, severity, message);
+  }
+  for child in &diagnostic.children {
+    diagnostic_lines(lines, child);
+  }
+}
+
+/// Elisp string literal
+fn quote(text: &str) -> String {
+  let mut quoted = String::with_capacity(text.len() + 2);
+  quoted.push('"');
+  for c in text.chars() {
+    if c == '"' || c == '\\' {
+      quoted.push('\\');
+    }
+    quoted.push(c);
+  }
+  quoted.push('"');
+  quoted
+}
+
+/// What `prin1` made of a string, emacsclient prints it that way
+fn unquote(printed: &str) -> String {
+  let inner = printed
+    .strip_prefix('"')
+    .and_then(|printed| printed.strip_suffix('"'))
+    .unwrap_or(printed);
+
+  let mut unquoted = String::with_capacity(inner.len());
+  let mut chars = inner.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' => unquoted.extend(chars.next()),
+      c => unquoted.push(c),
+    }
+  }
+  unquoted
+}
+
+#[test]
+fn emacs_test() {
+  use crate::types::{Entry, Location};
+
+  let location = |path: &str, line, column| Location {
+    path: path.to_string(),
+    line,
+    column,
+  };
+  let backtrace = Backtrace::with_entries(vec![
+    Entry::new(
+      "app::run".to_string(),
+      Some(location("/home/user/app/src/run.rs", 42, 5)),
+    ),
+    Entry::new("app::main".to_string(), None),
+  ])
+  .with_message("\"boom\"".to_string());
+
+  let report = Report::Backtrace(backtrace);
+  assert_eq!(
+    lines(&report),
+    "Panic\n  \"boom\"\n\n/home/user/app/src/run.rs:42:5: app::run\n"
+  );
+  assert!(elisp(&report, "/home/user/app/")
+    .contains("(insert \"Panic\n  \\\"boom\\\"\n"));
+
+  let Report::Backtrace(mut backtrace) = report else {
+    unreachable!();
+  };
+  backtrace.set_count(3);
+  let count = count_elisp(&backtrace);
+  assert!(count.contains("(insert \"Panic ×3\")"));
+  assert!(!count.contains("erase-buffer"));
+
+  assert_eq!(
+    unquote("\"/home/user/my \\\"app\\\"/\""),
+    "/home/user/my \"app\"/"
+  );
+}
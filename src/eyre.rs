@@ -0,0 +1,224 @@
+//! Parser for color-eyre reports and panics with their SPANTRACE and
+//! BACKTRACE sections
+//!
+//!   ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━ SPANTRACE ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+//!
+//!    0: myapp::handler with id=5
+//!       at src/handler.rs:40
+use crate::format::BacktraceFormat;
+use crate::types::{Backtrace, Entry, Label, Location};
+use crate::utils::strip_ansi;
+use nom::{
+  bytes::complete::{tag, take_until},
+  character::complete::{char, digit1, space0},
+  combinator::{map_res, opt},
+  sequence::{delimited, preceded, terminated, tuple},
+  IResult,
+};
+
+pub struct Eyre;
+
+impl BacktraceFormat for Eyre {
+  fn name(&self) -> &'static str {
+    "color-eyre"
+  }
+
+  /// `Error:` of a report returned from main or the color-eyre panic hook
+  fn is_start(&self, line: &str) -> bool {
+    let line = strip_ansi(line);
+    line.trim_end() == "Error:"
+      || line.starts_with("The application panicked (crashed).")
+  }
+
+  /// Sections are indented and separated by blank lines, the report ends
+  /// with the unindented `Run with ...` hints
+  fn is_continuation(&self, line: &str) -> bool {
+    let line = strip_ansi(line);
+    line.trim().is_empty()
+      || line.starts_with(char::is_whitespace)
+      || ["Location:", "Message:", "Suggestion:", "Note:", "Warning:"]
+        .iter()
+        .any(|section| line.starts_with(section))
+  }
+
+  fn parse(&self, i: &str) -> anyhow::Result<Vec<Backtrace>> {
+    Ok(parse_backtrace(i).into_iter().collect())
+  }
+}
+
+#[derive(PartialEq)]
+enum Section {
+  Errors,
+  Location,
+  SpanTrace,
+  Backtrace,
+}
+
+fn parse_backtrace(i: &str) -> Option<Backtrace> {
+  let mut section = None;
+  let mut errors = Vec::new();
+  let mut labels = Vec::new();
+  let mut spans: Vec<Entry> = Vec::new();
+  let mut entries: Vec<Entry> = Vec::new();
+
+  for line in i.lines().map(strip_ansi) {
+    let trimmed = line.trim();
+
+    if trimmed.starts_with('━') {
+      if trimmed.contains("SPANTRACE") {
+        section = Some(Section::SpanTrace);
+      } else if trimmed.contains("BACKTRACE") {
+        section = Some(Section::Backtrace);
+      }
+      continue;
+    }
+
+    if trimmed == "Error:" {
+      section = Some(Section::Errors);
+      continue;
+    }
+
+    if let Some(message) = line.strip_prefix("Message:") {
+      errors.push(message.trim().to_string());
+      continue;
+    }
+
+    // The location is either on the same line (panics) or on the next one
+    if let Some(location) = line.strip_prefix("Location:") {
+      match parse_location(location.trim()) {
+        Ok((_, location)) => labels.push(Label {
+          label: "Location".to_string(),
+          location,
+        }),
+        Err(_) => section = Some(Section::Location),
+      }
+      continue;
+    }
+
+    if section == Some(Section::Location) {
+      if let Ok((_, location)) = parse_location(trimmed) {
+        labels.push(Label {
+          label: "Location".to_string(),
+          location,
+        });
+      }
+      section = None;
+      continue;
+    }
+
+    let frames = match section {
+      Some(Section::SpanTrace) => &mut spans,
+      Some(Section::Backtrace) => &mut entries,
+      _ => {
+        if let Ok((_, error)) = parse_index(trimmed) {
+          errors.push(error.to_string());
+        }
+        continue;
+      }
+    };
+
+    if let Ok((_, location)) = preceded(tag("at "), parse_location)(trimmed) {
+      if let Some(frame) = frames.last_mut() {
+        frame.set_location(location);
+      }
+    } else if let Ok((_, frame)) = parse_index(trimmed) {
+      frames.push(parse_frame(frame));
+    } else if let Ok((_, count)) = parse_hidden(trimmed) {
+      frames.push(Entry::elided(count, "frames"));
+    }
+  }
+
+  if errors.is_empty() && entries.is_empty() && spans.is_empty() {
+    return None;
+  }
+
+  let backtrace = Backtrace::with_entries(entries)
+    .with_message(errors.join(": "))
+    .with_labels(labels)
+    .with_spans(spans);
+  Some(backtrace)
+}
+
+/// Spans can carry the location on the same line
+fn parse_frame(frame: &str) -> Entry {
+  if let Some((function, location)) = frame.rsplit_once(" at ") {
+    if let Ok((_, location)) = parse_location(location) {
+      return Entry::new(function.to_string(), Some(location));
+    }
+  }
+  Entry::new(frame.to_string(), None)
+}
+
+/// 6: myapp::read_config
+fn parse_index(i: &str) -> IResult<&str, &str> {
+  let (i, _) = terminated(digit1, tuple((char(':'), space0)))(i)?;
+  Ok(("", i))
+}
+
+/// ⋮ 5 frames hidden ⋮
+fn parse_hidden(i: &str) -> IResult<&str, usize> {
+  delimited(
+    tuple((tag("⋮"), space0)),
+    map_res(digit1, str::parse::<usize>),
+    tuple((space0, tag("frames hidden"), space0, tag("⋮"))),
+  )(i)
+}
+
+/// color-eyre doesn't print columns
+fn parse_location(i: &str) -> IResult<&str, Location> {
+  let (i, (path, _, line, column)) = tuple((
+    take_until(":"),
+    char(':'),
+    map_res(digit1, str::parse::<u32>),
+    opt(preceded(char(':'), map_res(digit1, str::parse::<u32>))),
+  ))(i)?;
+
+  let location = Location {
+    path: path.to_string(),
+    line,
+    column: column.unwrap_or(0),
+  };
+  Ok((i, location))
+}
+
+#[test]
+fn eyre_full_parser_test() {
+  let input = include_str!("./tests/fixtures/eyre.txt");
+  let mut lines = input.lines();
+  assert!(lines.next().is_some_and(|line| Eyre.is_start(line)));
+  let (report, hints): (Vec<_>, Vec<_>) =
+    lines.partition(|line| Eyre.is_continuation(line));
+  assert_eq!(hints.len(), 1);
+  assert!(!report.is_empty());
+
+  let backtrace = parse_backtrace(input).unwrap();
+  let entries = backtrace.entries();
+  assert_eq!(entries.len(), 4);
+  assert_eq!(entries[0], Entry::elided(5, "frames"));
+  assert_eq!(entries[3], Entry::elided(10, "frames"));
+  assert_eq!(backtrace.labels().len(), 1);
+
+  let spans = backtrace.spans();
+  assert_eq!(spans.len(), 2);
+  assert_eq!(
+    spans[0],
+    Entry::new(
+      "myapp::handler with id=5".to_string(),
+      Some(Location {
+        path: "src/handler.rs".to_string(),
+        line: 40,
+        column: 0,
+      })
+    )
+  );
+}
+
+#[test]
+fn eyre_panic_parser_test() {
+  let input = "The application panicked (crashed).\nMessage:  boom\nLocation: src/main.rs:4\n";
+  let backtrace = parse_backtrace(input).unwrap();
+  assert_eq!(
+    backtrace.labels()[0].location.to_string(),
+    "src/main.rs:4:0"
+  );
+}
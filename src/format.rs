@@ -0,0 +1,127 @@
+use crate::config::Config;
+use crate::types::Backtrace;
+use crate::{eyre::Eyre, gdb::Gdb, lldb::Lldb, miri::Miri, rust::Rust};
+
+/// A kind of backtrace that can be recognized in the stream
+pub trait BacktraceFormat {
+  fn name(&self) -> &'static str;
+
+  /// The first line of a backtrace, it's a part of the captured text
+  fn is_start(&self, line: &str) -> bool;
+
+  /// Backtraces without an end marker end with the first line that isn't a
+  /// part of them, that line is not captured
+  fn is_continuation(&self, line: &str) -> bool;
+
+  /// The last line of a backtrace, it's still captured
+  fn is_end(&self, _line: &str) -> bool {
+    false
+  }
+
+  /// Parses the captured text, starting with the start line
+  fn parse(&self, i: &str) -> anyhow::Result<Vec<Backtrace>>;
+
+  /// Formats that can be parsed frame by frame don't need the whole text
+  fn stream(&self) -> Option<Box<dyn Stream>> {
+    None
+  }
+}
+
+/// Incremental parser fed with the lines of one backtrace
+pub trait Stream {
+  fn push_line(&mut self, line: &str);
+
+  /// Whatever was parsed so far, the stream might have been cut off
+  fn finish(self: Box<Self>) -> Vec<Backtrace>;
+}
+
+/// Raw text kept for the history, streamed backtraces can be much longer
+const RAW_LIMIT: usize = 1 << 20;
+
+/// Lines of the backtrace being captured
+pub struct Capture<'a> {
+  format: &'a dyn BacktraceFormat,
+  lines: Lines,
+  /// The captured text as it was, cut off at `RAW_LIMIT`
+  raw: String,
+}
+
+enum Lines {
+  Buffered(String),
+  Stream(Box<dyn Stream>),
+}
+
+impl<'a> Capture<'a> {
+  pub fn new(format: &'a dyn BacktraceFormat) -> Self {
+    let lines = match format.stream() {
+      Some(stream) => Lines::Stream(stream),
+      None => Lines::Buffered(String::new()),
+    };
+    Self {
+      format,
+      lines,
+      raw: String::new(),
+    }
+  }
+
+  pub fn format(&self) -> &'a dyn BacktraceFormat {
+    self.format
+  }
+
+  pub fn push_line(&mut self, line: &str) {
+    if self.raw.len() + line.len() < RAW_LIMIT {
+      self.raw.push_str(line);
+      self.raw.push('\n');
+    }
+
+    match &mut self.lines {
+      Lines::Buffered(full) => {
+        full.push_str(line);
+        full.push('\n');
+      }
+      Lines::Stream(stream) => stream.push_line(line),
+    }
+  }
+
+  /// The backtraces and the raw text they were parsed from
+  pub fn finish(self) -> (anyhow::Result<Vec<Backtrace>>, String) {
+    let parsed = match self.lines {
+      Lines::Buffered(full) => self.format.parse(&full),
+      Lines::Stream(stream) => Ok(stream.finish()),
+    };
+    (parsed, self.raw)
+  }
+}
+
+/// Formats are tried in order, the first one to recognize a start wins
+///
+/// rustc diagnostics aren't in it: they're a list of diagnostics rather than
+/// a backtrace, they're passed through instead of replaced, and a panic can
+/// start while they're still collected, so `main` watches for them on the side
+pub struct Registry {
+  formats: Vec<Box<dyn BacktraceFormat>>,
+}
+
+impl Registry {
+  pub fn new(config: &Config) -> Self {
+    Self {
+      formats: vec![
+        Box::new(Rust {
+          max_frames: config.max_frames,
+        }),
+        Box::new(Miri),
+        Box::new(Eyre),
+        Box::new(Gdb),
+        Box::new(Lldb),
+      ],
+    }
+  }
+
+  pub fn detect(&self, line: &str) -> Option<&dyn BacktraceFormat> {
+    self
+      .formats
+      .iter()
+      .find(|format| format.is_start(line))
+      .map(AsRef::as_ref)
+  }
+}
@@ -0,0 +1,199 @@
+//! Parser for gdb `bt` and `thread apply all bt` output
+//!
+//! #1  0x000055555555d1f2 in foo::bar (x=1) at src/lib.rs:42
+use crate::format::BacktraceFormat;
+use crate::types::{Backtrace, Entry, Location};
+use nom::{
+  bytes::complete::{tag, take_until},
+  character::complete::{char, digit1, hex_digit1, space1},
+  combinator::{map_res, opt},
+  sequence::{delimited, tuple},
+  IResult,
+};
+
+pub struct Gdb;
+
+impl BacktraceFormat for Gdb {
+  fn name(&self) -> &'static str {
+    "gdb"
+  }
+
+  /// A thread header or the first frame of a single thread `bt`
+  fn is_start(&self, line: &str) -> bool {
+    parse_thread_header(line).is_ok() || line.starts_with("#0 ")
+  }
+
+  /// gdb doesn't mark the end of a backtrace, it lasts as long as the lines
+  /// look like frames, thread headers or the blank lines between them
+  fn is_continuation(&self, line: &str) -> bool {
+    line.is_empty()
+      || line.starts_with(char::is_whitespace)
+      || self.is_start(line)
+      || parse_index(line).is_ok()
+  }
+
+  fn parse(&self, i: &str) -> anyhow::Result<Vec<Backtrace>> {
+    Ok(parse_backtraces(i))
+  }
+}
+
+/// Every thread of the dump becomes its own backtrace
+fn parse_backtraces(i: &str) -> Vec<Backtrace> {
+  let mut backtraces = Vec::new();
+  let mut entries = Vec::new();
+
+  for line in join_wrapped(i) {
+    if parse_thread_header(&line).is_ok() && !entries.is_empty() {
+      backtraces.push(Backtrace::with_entries(std::mem::take(&mut entries)));
+    }
+
+    if let Ok((_, entry)) = parse_frame(&line) {
+      entries.push(entry);
+    }
+  }
+
+  if !entries.is_empty() {
+    backtraces.push(Backtrace::with_entries(entries));
+  }
+
+  backtraces
+}
+
+/// Long frames are wrapped with the location indented on the next line
+fn join_wrapped(i: &str) -> Vec<String> {
+  let mut lines: Vec<String> = Vec::new();
+  for line in i.lines() {
+    match lines.last_mut() {
+      Some(last) if line.starts_with(char::is_whitespace) => {
+        last.push(' ');
+        last.push_str(line.trim());
+      }
+      _ => lines.push(line.to_string()),
+    }
+  }
+  lines
+}
+
+fn parse_thread_header(i: &str) -> IResult<&str, &str> {
+  let (i, (_, index, _)) = tuple((tag("Thread "), digit1, tag(" (")))(i)?;
+  Ok((i, index))
+}
+
+fn parse_index(i: &str) -> IResult<&str, usize> {
+  let (i, (_, index, _)) =
+    tuple((char('#'), map_res(digit1, str::parse::<usize>), space1))(i)?;
+  Ok((i, index))
+}
+
+fn parse_frame(i: &str) -> IResult<&str, Entry> {
+  // The address is missing when the frame is at the start of a line, gdb
+  // leaves it out for inlined frames too
+  let (i, (index, address)) = tuple((
+    parse_index,
+    opt(delimited(
+      tag("0x"),
+      map_res(hex_digit1, |a| u64::from_str_radix(a, 16)),
+      tag(" in "),
+    )),
+  ))(i)?;
+
+  let (function, location) = split_location(i);
+  let function = strip_arguments(function);
+
+  let entry = Entry::new(function.to_string(), location).with_index(index);
+  match address {
+    Some(address) => Ok(("", entry.with_address(address))),
+    None => Ok(("", entry)),
+  }
+}
+
+/// Splits off the trailing ` at path:line` or ` from library`
+fn split_location(i: &str) -> (&str, Option<Location>) {
+  match (i.rfind(" at "), i.rfind(" from ")) {
+    (Some(at), from) if from.is_none_or(|from| at > from) => {
+      let location = parse_location(&i[at + 4..]).ok().map(|(_, l)| l);
+      (&i[..at], location)
+    }
+    (_, Some(from)) => (&i[..from], None),
+    _ => (i, None),
+  }
+}
+
+/// gdb doesn't print columns
+fn parse_location(i: &str) -> IResult<&str, Location> {
+  let (i, (path, _, line)) = tuple((
+    take_until(":"),
+    char(':'),
+    map_res(digit1, str::parse::<u32>),
+  ))(i)?;
+
+  let location = Location {
+    path: path.to_string(),
+    line,
+    column: 0,
+  };
+  Ok((i, location))
+}
+
+/// Drops the `(arg=..., ...)` list following the function name
+fn strip_arguments(i: &str) -> &str {
+  let i = i.trim_end();
+  if !i.ends_with(')') {
+    return i;
+  }
+
+  let mut depth = 0;
+  for (index, c) in i.char_indices().rev() {
+    match c {
+      ')' => depth += 1,
+      '(' => {
+        depth -= 1;
+        if depth == 0 {
+          return i[..index].trim_end();
+        }
+      }
+      _ => {}
+    }
+  }
+  i
+}
+
+#[test]
+fn gdb_frame_parser_test() {
+  let input =
+    "#1  0x000055555555d1f2 in foo::bar (a=(1, 2), b=...) at src/lib.rs:42";
+  let (_i, entry) = parse_frame(input).unwrap();
+  assert_eq!(
+    entry,
+    Entry::new(
+      "foo::bar".to_string(),
+      Some(Location {
+        path: "src/lib.rs".to_string(),
+        line: 42,
+        column: 0,
+      })
+    )
+    .with_index(1)
+    .with_address(0x55555555d1f2)
+  );
+
+  let input = "#3  0x00007ffff7e29d90 in ?? () from /lib/libc.so.6";
+  let (_i, entry) = parse_frame(input).unwrap();
+  assert_eq!(
+    entry,
+    Entry::new("??".to_string(), None)
+      .with_index(3)
+      .with_address(0x7ffff7e29d90)
+  );
+}
+
+#[test]
+fn gdb_full_parser_test() {
+  let input = include_str!("./tests/fixtures/gdb.txt");
+  assert!(input.lines().all(|line| Gdb.is_continuation(line)));
+
+  let backtraces = parse_backtraces(input);
+  assert_eq!(backtraces.len(), 2);
+  assert_eq!(backtraces[0].entries().len(), 5);
+  assert_eq!(backtraces[1].entries().len(), 4);
+}
@@ -0,0 +1,285 @@
+//! Backtraces stored under `$XDG_STATE_HOME/stacky`, `stacky history`
+//!
+//! Every backtrace is a `<id>.json` file with the text it was parsed from.
+use crate::config::Config;
+use crate::types::Backtrace;
+use crate::utils::{format_time, run};
+use anyhow::{anyhow, Context};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+  env,
+  fs::{self, OpenOptions},
+  io::{self, Write},
+  path::{Path, PathBuf},
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// `B` is borrowed when a record is saved and owned when it's loaded
+#[derive(Serialize, Deserialize)]
+pub struct Record<B> {
+  pub id: u64,
+  pub timestamp: u64,
+  /// What produced the input, from `--command`
+  pub command: Option<String>,
+  pub cwd: String,
+  /// git HEAD of the cwd
+  pub head: Option<String>,
+  pub raw: String,
+  pub backtrace: B,
+}
+
+impl Record<Backtrace> {
+  /// The backtrace is resolved against the cwd it came from, it's stored
+  /// unfiltered
+  pub fn from_json(json: &str) -> anyhow::Result<Self> {
+    let mut record: Self = serde_json::from_str(json)?;
+    record.backtrace.resolve(Path::new(&record.cwd));
+    Ok(record)
+  }
+}
+
+/// Where the backtraces of a run come from, stored with each of them
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Origin {
+  /// What produced the input, from `--command`
+  pub command: Option<String>,
+  pub cwd: String,
+  /// git HEAD of the cwd
+  pub head: Option<String>,
+}
+
+impl Origin {
+  pub fn current(config: &Config) -> Self {
+    let cwd = env::current_dir()
+      .map(|cwd| cwd.to_string_lossy().into_owned())
+      .unwrap_or_default();
+
+    Self {
+      command: config.command_label.clone(),
+      cwd,
+      head: run("git", &["rev-parse", "HEAD"]),
+    }
+  }
+}
+
+pub struct History {
+  dir: PathBuf,
+  limit: usize,
+  max_age: Option<Duration>,
+  origin: Origin,
+}
+
+impl History {
+  pub fn open(config: &Config) -> anyhow::Result<Self> {
+    let state = env::var_os("XDG_STATE_HOME")
+      .filter(|dir| !dir.is_empty())
+      .map(PathBuf::from)
+      .or_else(|| {
+        env::var_os("HOME").map(|home| Path::new(&home).join(".local/state"))
+      })
+      .ok_or_else(|| anyhow!("neither XDG_STATE_HOME nor HOME is set"))?;
+    let dir = state.join("stacky");
+    fs::create_dir_all(&dir)
+      .with_context(|| format!("creating {}", dir.display()))?;
+
+    Ok(Self {
+      dir,
+      limit: config.history_limit,
+      max_age: config
+        .history_days
+        .map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+      origin: Origin::current(config),
+    })
+  }
+
+  /// Stored ids, oldest first
+  fn ids(&self) -> Vec<u64> {
+    let mut ids: Vec<u64> = fs::read_dir(&self.dir)
+      .map(|dir| {
+        dir
+          .filter_map(Result::ok)
+          .filter_map(|entry| {
+            let name = entry.file_name();
+            name.to_str()?.strip_suffix(".json")?.parse().ok()
+          })
+          .collect()
+      })
+      .unwrap_or_default();
+    ids.sort_unstable();
+    ids
+  }
+
+  fn path(&self, id: u64) -> PathBuf {
+    self.dir.join(format!("{}.json", id))
+  }
+
+  pub fn save(&self, backtrace: &Backtrace, raw: &str) -> anyhow::Result<u64> {
+    self.save_from(&self.origin, backtrace, raw)
+  }
+
+  /// Saves a backtrace another stacky process parsed, `stacky daemon`
+  pub fn save_from(
+    &self,
+    origin: &Origin,
+    backtrace: &Backtrace,
+    raw: &str,
+  ) -> anyhow::Result<u64> {
+    let mut id = self.ids().last().map_or(1, |last| last + 1);
+    let timestamp = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map_or(0, |now| now.as_secs());
+
+    let mut record = Record {
+      id,
+      timestamp,
+      command: origin.command.clone(),
+      cwd: origin.cwd.clone(),
+      head: origin.head.clone(),
+      raw: raw.to_string(),
+      backtrace,
+    };
+    // Another stacky or the daemon can save one at the same time, the id
+    // goes to whoever creates the file first
+    loop {
+      record.id = id;
+      let created = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(self.path(id));
+      match created {
+        Ok(mut file) => {
+          file.write_all(serde_json::to_string(&record)?.as_bytes())?;
+          break;
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => id += 1,
+        Err(e) => return Err(e.into()),
+      }
+    }
+
+    self.prune();
+    Ok(id)
+  }
+
+  /// The backtrace is filtered like a fresh one
+  pub fn load(&self, id: u64) -> anyhow::Result<Record<Backtrace>> {
+    let mut record = self.load_full(id)?;
+    record.backtrace.filter();
+    Ok(record)
+  }
+
+  /// All the frames, filtered or not
+  pub fn load_full(&self, id: u64) -> anyhow::Result<Record<Backtrace>> {
+    let path = self.path(id);
+    let json = fs::read_to_string(&path)
+      .with_context(|| format!("no backtrace {} in the history", id))?;
+    Record::from_json(&json)
+      .with_context(|| format!("reading {}", path.display()))
+  }
+
+  /// Drops the records over the limit and the ones that are too old
+  fn prune(&self) {
+    let ids = self.ids();
+    let over = ids.len().saturating_sub(self.limit);
+
+    for (index, id) in ids.into_iter().enumerate() {
+      let path = self.path(id);
+      let expired = self.max_age.is_some_and(|max_age| {
+        fs::metadata(&path)
+          .and_then(|metadata| metadata.modified())
+          .ok()
+          .and_then(|modified| modified.elapsed().ok())
+          .is_some_and(|age| age > max_age)
+      });
+
+      if index < over || expired {
+        let _ = fs::remove_file(path);
+      }
+    }
+  }
+
+  /// Newest first, one line each
+  pub fn list(&self) -> anyhow::Result<()> {
+    for id in self.ids().into_iter().rev() {
+      let Ok(record) = self.load(id) else {
+        continue;
+      };
+      let summary = record
+        .backtrace
+        .message()
+        .and_then(|message| message.lines().next())
+        .or_else(|| {
+          let entries = record.backtrace.entries();
+          entries.first().map(|entry| entry.function())
+        })
+        .unwrap_or("");
+      println!(
+        "{:>5}  {}  {}  {}",
+        record.id,
+        format_time(record.timestamp),
+        record.cwd,
+        summary
+      );
+    }
+    Ok(())
+  }
+
+  pub fn show(&self, id: u64) -> anyhow::Result<()> {
+    let record = self.load(id)?;
+    println!("id:      {}", record.id);
+    println!("time:    {} UTC", format_time(record.timestamp));
+    if let Some(command) = &record.command {
+      println!("command: {}", command);
+    }
+    println!("cwd:     {}", record.cwd);
+    if let Some(head) = &record.head {
+      println!("head:    {}", head);
+    }
+    println!("{}", record.backtrace);
+    print!("{}", record.raw);
+    Ok(())
+  }
+}
+
+#[test]
+fn history_test() {
+  use crate::types::{Entry, Location};
+
+  let dir =
+    env::temp_dir().join(format!("stacky-history-{}", std::process::id()));
+  let history = History {
+    dir: dir.clone(),
+    limit: 2,
+    max_age: None,
+    origin: Origin {
+      command: Some("cargo test".to_string()),
+      cwd: "/home/user/app".to_string(),
+      head: None,
+    },
+  };
+  fs::create_dir_all(&dir).unwrap();
+
+  let frame = |function: &str| {
+    let location = Location {
+      path: "src/main.rs".to_string(),
+      line: 4,
+      column: 5,
+    };
+    Entry::new(function.to_string(), Some(location))
+  };
+  let run = Backtrace::with_entries(vec![frame("app::run")]);
+  let spin = Backtrace::with_entries(vec![frame("app::spin")]);
+  for backtrace in [&run, &spin, &run] {
+    history.save(backtrace, "raw").unwrap();
+  }
+
+  assert_eq!(history.ids(), [2, 3]);
+  let record = history.load(3).unwrap();
+  let entries = record.backtrace.entries();
+  assert_eq!(entries.len(), 1);
+  assert_eq!(entries[0].function(), "app::run");
+  let location = entries[0].location().unwrap();
+  assert_eq!(location.path, "/home/user/app/src/main.rs");
+  assert_eq!(record.raw, "raw");
+
+  fs::remove_dir_all(dir).unwrap();
+}
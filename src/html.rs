@@ -0,0 +1,224 @@
+//! Single file HTML report of the backtraces, `--html`
+//!
+//! Frames expand to their source, they're colored by where they come from
+//! and a checkbox shows the ones the filter leaves out. It's plain CSS, the
+//! file works offline.
+use crate::types::{Backtrace, Entry, Source};
+use std::{fmt::Write, fs, path::Path};
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; background: #1e1e1e; color: #ddd; }
+h2 { color: #f66; margin-bottom: 0.2em; }
+.meta { color: #999; margin: 0.2em 0; }
+pre { background: #111; padding: 0.5em; overflow-x: auto; }
+details { margin: 0.1em 0; }
+summary { cursor: pointer; font-family: monospace; }
+.inlined { margin-left: 2em; }
+.user summary { color: #6cf; }
+.dependency summary { color: #ca6; }
+.std summary, .elided { color: #777; }
+.path { color: #8c8; }
+.line { color: #fc6; }
+.failing { background: #533; }
+.filtered { display: none; }
+#unfiltered:checked ~ .backtrace .filtered { display: block; }
+";
+
+/// Where a frame comes from
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+  User,
+  Dependency,
+  Std,
+}
+
+impl Kind {
+  fn of(entry: &Entry) -> Self {
+    let function = entry.function().trim_start_matches(['<', '&']);
+    let Some(location) = entry.location() else {
+      return Self::Std;
+    };
+    let path = &location.path;
+
+    if path.contains("/rustc/")
+      || ["std::", "core::", "alloc::"]
+        .iter()
+        .any(|prefix| function.starts_with(prefix))
+    {
+      Self::Std
+    } else if path.contains("/.cargo/registry/")
+      || path.contains("/.cargo/git/")
+    {
+      Self::Dependency
+    } else {
+      Self::User
+    }
+  }
+
+  fn class(self) -> &'static str {
+    match self {
+      Self::User => "user",
+      Self::Dependency => "dependency",
+      Self::Std => "std",
+    }
+  }
+}
+
+#[derive(Default)]
+pub struct Html {
+  backtraces: String,
+}
+
+impl Html {
+  /// Takes the backtrace before it's filtered, relative paths are read
+  /// from `root`
+  pub fn push(&mut self, backtrace: &Backtrace, root: &Path) {
+    let html = &mut self.backtraces;
+    html.push_str("<section class=\"backtrace\">\n");
+
+    let message = backtrace.message().unwrap_or("Backtrace");
+    let _ = writeln!(html, "<h2>{}</h2>", escape(message));
+    let meta = [
+      ("Test", backtrace.test().map(str::to_string)),
+      ("Thread", backtrace.thread().map(str::to_string)),
+      ("Assertion", backtrace.assertion().map(|a| a.to_string())),
+    ];
+    for (name, value) in meta {
+      if let Some(value) = value {
+        let _ = writeln!(
+          html,
+          "<div class=\"meta\">{}: <code>{}</code></div>",
+          name,
+          escape(&value)
+        );
+      }
+    }
+
+    // Only the frames under the root get their source, the paths are shown
+    // as they were printed
+    let mut resolved = backtrace.clone();
+    resolved.resolve(root);
+    resolved.load_sources(root, CONTEXT);
+    let sources = resolved.entries().iter().map(Entry::source);
+
+    let kept = backtrace.kept();
+    for ((entry, kept), code) in
+      backtrace.entries().iter().zip(kept).zip(sources)
+    {
+      let filtered = if kept { "" } else { " filtered" };
+
+      if entry.elided_count() > 0 {
+        let _ = writeln!(
+          html,
+          "<div class=\"elided{}\">… {} {} …</div>",
+          filtered,
+          entry.elided_count(),
+          escape(entry.function())
+        );
+        continue;
+      }
+
+      let inlined = if entry.is_inlined() { " inlined" } else { "" };
+      let _ = write!(
+        html,
+        "<details class=\"{}{}{}\"><summary>{}",
+        Kind::of(entry).class(),
+        inlined,
+        filtered,
+        escape(entry.function())
+      );
+
+      let Some(location) = entry.location() else {
+        html.push_str("</summary></details>\n");
+        continue;
+      };
+      let _ = write!(
+        html,
+        " <span class=\"path\">{}</span>:<span class=\"line\">{}:{}</span></summary>",
+        escape(&location.path),
+        location.line,
+        location.column
+      );
+      if let Some(code) = code {
+        html.push_str(&source(code, location.line));
+      }
+      html.push_str("</details>\n");
+    }
+
+    html.push_str("</section>\n");
+  }
+
+  pub fn render(&self) -> String {
+    format!(
+      "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+       <title>stacky</title>\n<style>{}</style>\n</head>\n<body>\n\
+       <input type=\"checkbox\" id=\"unfiltered\">\
+       <label for=\"unfiltered\">Show all frames</label>\n{}</body>\n</html>\n",
+      STYLE, self.backtraces
+    )
+  }
+
+  pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+    fs::write(path, self.render())?;
+    Ok(())
+  }
+}
+
+/// Lines of source above and below the location of a frame
+const CONTEXT: usize = 5;
+
+/// Lines around the location with the failing one highlighted
+fn source(source: &Source, failing: u32) -> String {
+  let mut html = String::from("<pre>");
+  for (number, code) in (source.first..).zip(&source.lines) {
+    let line = format!("{:>5} | {}", number, escape(code));
+    if number == failing {
+      let _ = writeln!(html, "<span class=\"failing\">{}</span>", line);
+    } else {
+      let _ = writeln!(html, "{}", line);
+    }
+  }
+  html.push_str("</pre>");
+  html
+}
+
+fn escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+#[test]
+fn html_test() {
+  use crate::{format::BacktraceFormat, rust::Rust};
+
+  let input = include_str!("./tests/fixtures/panic.txt");
+  let input = format!("stack backtrace:\n{}", input);
+  let mut html = Html::default();
+  for backtrace in Rust::default().parse(&input).unwrap() {
+    html.push(&backtrace, Path::new("/"));
+  }
+
+  let page = html.render();
+  assert!(page.starts_with("<!DOCTYPE html>"));
+  assert!(page.contains("class=\"std filtered\""));
+  assert!(
+    page.contains("&lt;std::sys_common::backtrace::_print::DisplayBacktrace")
+  );
+
+  // Only the frames under the root embed their source
+  let location = |path: &str| crate::types::Location {
+    path: path.to_string(),
+    line: 1,
+    column: 1,
+  };
+  let backtrace = Backtrace::with_entries(vec![
+    Entry::new("app::main".to_string(), Some(location("src/html.rs"))),
+    Entry::new("std::rt".to_string(), Some(location("/etc/passwd"))),
+  ]);
+  let mut html = Html::default();
+  html.push(&backtrace, Path::new(env!("CARGO_MANIFEST_DIR")));
+  assert_eq!(html.render().matches("<pre>").count(), 1);
+}
@@ -0,0 +1,165 @@
+//! Markdown bug report of the last backtrace, `stacky report`
+use crate::types::{Backtrace, Entry};
+use crate::utils::run;
+use std::{
+  fmt::Write,
+  path::{Path, PathBuf},
+};
+
+/// Where the backtrace came from, filled in from rustc and git
+pub struct Environment {
+  pub rustc: Option<String>,
+  pub target: Option<String>,
+  pub commit: Option<String>,
+  /// Links are relative to the root of the repository
+  pub root: PathBuf,
+}
+
+impl Environment {
+  pub fn detect(cwd: &Path) -> Self {
+    let verbose = run("rustc", &["-vV"]);
+    let target = verbose.as_deref().and_then(|verbose| {
+      verbose
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(str::to_string)
+    });
+
+    Self {
+      rustc: verbose.and_then(|v| v.lines().next().map(str::to_string)),
+      target,
+      commit: run("git", &["rev-parse", "--short", "HEAD"]),
+      root: run("git", &["rev-parse", "--show-toplevel"])
+        .map(PathBuf::from)
+        .unwrap_or_else(|| cwd.to_path_buf()),
+    }
+  }
+}
+
+/// The frames before they are filtered, for the collapsed full trace
+pub fn full_trace(backtrace: &Backtrace) -> String {
+  let mut full = String::new();
+  for entry in backtrace.entries() {
+    let _ = match entry.location() {
+      Some(location) => {
+        writeln!(full, "{}\n    at {}", entry.function(), location)
+      }
+      None => writeln!(full, "{}", entry.function()),
+    };
+  }
+  full
+}
+
+pub fn render(
+  backtrace: &Backtrace,
+  full_trace: &str,
+  environment: &Environment,
+) -> String {
+  let mut report = String::new();
+  let message = backtrace.message().unwrap_or("The program panicked");
+  let _ = writeln!(report, "## {}\n", message.lines().next().unwrap_or(""));
+
+  if message.contains('\n') {
+    let _ = writeln!(report, "```text\n{}\n```\n", message);
+  }
+
+  let details = [
+    ("Test", backtrace.test()),
+    ("Thread", backtrace.thread()),
+    ("rustc", environment.rustc.as_deref()),
+    ("Target", environment.target.as_deref()),
+    ("Commit", environment.commit.as_deref()),
+  ];
+  report.push_str("| | |\n|---|---|\n");
+  for (name, value) in details {
+    if let Some(value) = value {
+      let _ = writeln!(report, "| {} | `{}` |", name, escape(value));
+    }
+  }
+
+  report
+    .push_str("\n### Frames\n\n| # | Function | Location |\n|---|---|---|\n");
+  // The full trace has the others
+  let mut frames: Vec<_> = backtrace
+    .entries()
+    .iter()
+    .enumerate()
+    .filter(|(_, entry)| is_user(entry, &environment.root))
+    .collect();
+  if frames.is_empty() {
+    frames = backtrace.entries().iter().enumerate().collect();
+  }
+  for (number, entry) in frames {
+    let _ = writeln!(
+      report,
+      "| {} | `{}` | {} |",
+      number,
+      escape(entry.function()),
+      link(entry, &environment.root)
+    );
+  }
+
+  let _ = write!(
+    report,
+    "\n<details>\n<summary>Full backtrace</summary>\n\n```text\n{}```\n\n</details>\n",
+    full_trace
+  );
+  report
+}
+
+/// `[src/main.rs:30:7](src/main.rs#L30)`
+fn link(entry: &Entry, root: &Path) -> String {
+  let Some(location) = entry.location() else {
+    return String::new();
+  };
+
+  let path = Path::new(&location.path);
+  match path.strip_prefix(root) {
+    Ok(relative) => {
+      let relative = relative.to_string_lossy();
+      format!(
+        "[{}:{}:{}]({}#L{})",
+        relative, location.line, location.column, relative, location.line
+      )
+    }
+    Err(_) => format!("`{}`", location),
+  }
+}
+
+/// Frames in the repository, linked relative to its root
+fn is_user(entry: &Entry, root: &Path) -> bool {
+  entry
+    .location()
+    .is_some_and(|location| Path::new(&location.path).starts_with(root))
+}
+
+/// Pipes would end the table cell
+fn escape(text: &str) -> String {
+  text.replace('|', "\\|")
+}
+
+#[test]
+fn report_test() {
+  use crate::{format::BacktraceFormat, lldb::Lldb};
+
+  let input = include_str!("./tests/fixtures/lldb.txt");
+  let backtrace = Lldb.parse(input).unwrap().pop().unwrap();
+  let backtrace = backtrace.with_message("boom".to_string());
+  let environment = Environment {
+    rustc: Some("rustc 1.80.0".to_string()),
+    target: None,
+    commit: Some("abc1234".to_string()),
+    root: PathBuf::from("/home/user/myapp"),
+  };
+
+  let report = render(&backtrace, &full_trace(&backtrace), &environment);
+  assert!(report.starts_with("## boom\n"));
+  assert!(report.contains("| Commit | `abc1234` |"));
+  assert!(!report.contains("| Target |"));
+  assert!(report.contains("[src/worker.rs:17:9](src/worker.rs#L17)"));
+  // Frames outside the repository are only in the full trace
+  let frames = report.split("<details>").next().unwrap();
+  assert!(!frames.contains("__poll"));
+  assert!(report.contains("__poll"));
+  assert!(report.contains("<details>"));
+}
@@ -0,0 +1,174 @@
+//! Parser for lldb `bt` and `bt all` output
+//!
+//!   frame #1: 0x000055555555d1f2 prog`foo::bar at lib.rs:42:5
+use crate::format::BacktraceFormat;
+use crate::types::{Backtrace, Entry, Location};
+use nom::{
+  bytes::complete::{tag, take_until},
+  character::complete::{char, digit1, hex_digit1, space0},
+  combinator::{map_res, opt},
+  sequence::{preceded, tuple},
+  IResult,
+};
+
+pub struct Lldb;
+
+impl BacktraceFormat for Lldb {
+  fn name(&self) -> &'static str {
+    "lldb"
+  }
+
+  /// The selected thread is marked with `*`, the others are only indented
+  fn is_start(&self, line: &str) -> bool {
+    parse_thread_header(line.trim_start()).is_ok()
+  }
+
+  /// lldb doesn't mark the end of a backtrace, it lasts as long as the lines
+  /// look like frames, thread headers or the blank lines between them
+  fn is_continuation(&self, line: &str) -> bool {
+    line.trim().is_empty()
+      || self.is_start(line)
+      || parse_index(line.trim_start()).is_ok()
+  }
+
+  fn parse(&self, i: &str) -> anyhow::Result<Vec<Backtrace>> {
+    Ok(parse_backtraces(i))
+  }
+}
+
+/// Every thread of the dump becomes its own backtrace
+fn parse_backtraces(i: &str) -> Vec<Backtrace> {
+  let mut backtraces = Vec::new();
+  let mut entries = Vec::new();
+
+  for line in i.lines().map(str::trim_start) {
+    if parse_thread_header(line).is_ok() && !entries.is_empty() {
+      backtraces.push(Backtrace::with_entries(std::mem::take(&mut entries)));
+    }
+
+    if let Ok((_, entry)) = parse_frame(line) {
+      entries.push(entry);
+    }
+  }
+
+  if !entries.is_empty() {
+    backtraces.push(Backtrace::with_entries(entries));
+  }
+
+  backtraces
+}
+
+fn parse_thread_header(i: &str) -> IResult<&str, &str> {
+  let (i, (_, _, index)) = tuple((opt(tag("* ")), tag("thread #"), digit1))(i)?;
+  Ok((i, index))
+}
+
+fn parse_index(i: &str) -> IResult<&str, usize> {
+  let (i, (_, _, index, _)) = tuple((
+    opt(tag("* ")),
+    tag("frame #"),
+    map_res(digit1, str::parse::<usize>),
+    tag(": "),
+  ))(i)?;
+  Ok((i, index))
+}
+
+fn parse_frame(i: &str) -> IResult<&str, Entry> {
+  let (i, (index, _, address, _)) = tuple((
+    parse_index,
+    tag("0x"),
+    map_res(hex_digit1, |a| u64::from_str_radix(a, 16)),
+    space0,
+  ))(i)?;
+
+  // module`symbol, frames without symbols only have the module or nothing
+  let symbol = i.split_once('`').map_or(i, |(_module, symbol)| symbol);
+  let symbol = strip_annotations(symbol);
+
+  let (symbol, location) = match symbol.rsplit_once(" at ") {
+    Some((symbol, location)) => {
+      (symbol, parse_location(location).ok().map(|(_, l)| l))
+    }
+    None => (symbol, None),
+  };
+
+  // Frames without debug info have an offset into the symbol instead
+  let function = match symbol.rsplit_once(" + ") {
+    Some((function, offset)) if offset.chars().all(|c| c.is_ascii_digit()) => {
+      function
+    }
+    _ => symbol,
+  };
+
+  let entry = Entry::new(function.to_string(), location)
+    .with_index(index)
+    .with_address(address);
+  Ok(("", entry))
+}
+
+/// Drops the trailing `[opt]`, `[inlined]` and `[artificial]` markers
+fn strip_annotations(mut i: &str) -> &str {
+  while let Some(stripped) = i
+    .strip_suffix(']')
+    .and_then(|i| i.rfind(" [").map(|index| &i[..index]))
+  {
+    i = stripped;
+  }
+  i
+}
+
+/// The column is omitted when it's not known
+fn parse_location(i: &str) -> IResult<&str, Location> {
+  let (i, (path, _, line, column)) = tuple((
+    take_until(":"),
+    char(':'),
+    map_res(digit1, str::parse::<u32>),
+    opt(preceded(char(':'), map_res(digit1, str::parse::<u32>))),
+  ))(i)?;
+
+  let location = Location {
+    path: path.to_string(),
+    line,
+    column: column.unwrap_or(0),
+  };
+  Ok((i, location))
+}
+
+#[test]
+fn lldb_frame_parser_test() {
+  let input = "frame #1: 0x000055555555d1f2 prog`foo::bar::h8c3a0e6b5a6c1f2d at lib.rs:42:5 [opt] [inlined]";
+  let (_i, entry) = parse_frame(input).unwrap();
+  assert_eq!(
+    entry,
+    Entry::new(
+      "foo::bar::h8c3a0e6b5a6c1f2d".to_string(),
+      Some(Location {
+        path: "lib.rs".to_string(),
+        line: 42,
+        column: 5,
+      })
+    )
+    .with_index(1)
+    .with_address(0x55555555d1f2)
+  );
+
+  let input = "* frame #0: 0x00007ffff7e9a9dd libc.so.6`__poll + 77";
+  let (_i, entry) = parse_frame(input).unwrap();
+  assert_eq!(
+    entry,
+    Entry::new("__poll".to_string(), None)
+      .with_index(0)
+      .with_address(0x7ffff7e9a9dd)
+  );
+}
+
+#[test]
+fn lldb_full_parser_test() {
+  let input = include_str!("./tests/fixtures/lldb.txt");
+  assert!(input.lines().all(|line| Lldb.is_continuation(line)));
+
+  let backtraces = parse_backtraces(input);
+  assert_eq!(backtraces.len(), 2);
+  assert_eq!(backtraces[0].entries().len(), 5);
+  assert_eq!(backtraces[1].entries().len(), 2);
+}
@@ -0,0 +1,492 @@
+//! `stacky lsp`, a language server publishing the backtraces as diagnostics
+//! on the lines of their frames, with a code lens where they panicked
+//!
+//! Piped stacky processes hand it their backtraces through a socket in
+//! `$XDG_RUNTIME_DIR/stacky`, one JSON `Report` per line.
+use crate::types::{Backtrace, Location, Report};
+use crate::utils::{encode_path, runtime_dir};
+use lsp_server::{
+  Connection, ErrorCode, Message, Notification, Request, RequestId, Response,
+};
+use lsp_types::{
+  self as lsp,
+  notification::{Notification as _, PublishDiagnostics},
+  request::{CodeLensRequest, ExecuteCommand, Request as _},
+  CodeLens, CodeLensOptions, CodeLensParams, DiagnosticSeverity,
+  ExecuteCommandOptions, ExecuteCommandParams, Position,
+  PublishDiagnosticsParams, Range, ServerCapabilities, Uri,
+};
+use serde_json::Value;
+use std::{
+  collections::{BTreeMap, HashSet},
+  fs,
+  io::{BufRead, BufReader, Write},
+  os::unix::net::{UnixListener, UnixStream},
+  path::PathBuf,
+  sync::mpsc,
+  thread,
+  time::{Duration, SystemTime},
+};
+
+/// Clears one panic, given its fingerprint, or all of them
+const CLEAR: &str = "stacky.clear";
+
+/// Panics shown at once, the oldest go first
+const MAX_PANICS: usize = 20;
+
+/// Sockets of the running servers
+fn sockets() -> Vec<PathBuf> {
+  let Ok(dir) = runtime_dir().and_then(fs::read_dir) else {
+    return Vec::new();
+  };
+  dir
+    .filter_map(Result::ok)
+    .map(|entry| entry.path())
+    .filter(|path| {
+      let name = path.file_name().and_then(|name| name.to_str());
+      name.is_some_and(|name| name.starts_with("lsp-"))
+    })
+    .collect()
+}
+
+/// Hands the report to every language server, they pick what's theirs
+pub fn deliver(report: &Report, verbose: bool) {
+  let Ok(json) = serde_json::to_string(report) else {
+    return;
+  };
+
+  for socket in sockets() {
+    if verbose {
+      eprintln!("lsp socket: {:?}", socket);
+    }
+    match UnixStream::connect(&socket) {
+      Ok(mut stream) => {
+        if let Err(e) = writeln!(stream, "{}", json) {
+          eprintln!("Stacky error sending backtrace to {:?}: {}", socket, e);
+        }
+      }
+      // Left behind by a server that didn't exit cleanly
+      Err(_) => {
+        let _ = fs::remove_file(&socket);
+      }
+    }
+  }
+}
+
+enum Event {
+  Message(Message),
+  Report(Report),
+}
+
+struct Panic {
+  backtrace: Backtrace,
+  fingerprint: String,
+  time: SystemTime,
+}
+
+struct Server {
+  /// Workspace of the editor, backtraces without a location in it are left
+  /// out
+  root: Option<String>,
+  panics: Vec<Panic>,
+  /// Files with diagnostics, they're cleared when their panics are gone
+  published: HashSet<String>,
+  /// The client asks for the code lenses again when told to
+  refresh: bool,
+  next_id: i32,
+}
+
+impl Server {
+  fn new(params: &Value) -> Self {
+    let root = params["workspaceFolders"][0]["uri"]
+      .as_str()
+      .or_else(|| params["rootUri"].as_str())
+      .and_then(uri_path);
+    let refresh = &params["capabilities"]["workspace"]["codeLens"];
+
+    Self {
+      root,
+      panics: Vec::new(),
+      published: HashSet::new(),
+      refresh: refresh["refreshSupport"].as_bool().unwrap_or(false),
+      next_id: 0,
+    }
+  }
+
+  fn in_root(&self, path: &str) -> bool {
+    self.root.as_ref().is_none_or(|root| path.starts_with(root))
+  }
+
+  /// Backtraces only, the compiler errors are rust-analyzer's
+  fn report(&mut self, report: Report, connection: &Connection) {
+    let Report::Backtrace(mut backtrace) = report else {
+      return;
+    };
+    if let Some(root) = &self.root {
+      if !backtrace.has_location(root) {
+        return;
+      }
+    }
+
+    // A repeat replaces the panic, it has the new count
+    let fingerprint = backtrace.fingerprint().to_string();
+    self.panics.retain(|panic| panic.fingerprint != fingerprint);
+    self.panics.push(Panic {
+      backtrace,
+      fingerprint,
+      time: SystemTime::now(),
+    });
+    if self.panics.len() > MAX_PANICS {
+      self.panics.remove(0);
+    }
+
+    self.publish(connection);
+  }
+
+  /// Diagnostics of every file with a frame in it, newest panics first
+  fn diagnostics(&self) -> BTreeMap<String, Vec<lsp::Diagnostic>> {
+    let mut files: BTreeMap<String, Vec<lsp::Diagnostic>> = BTreeMap::new();
+
+    for panic in self.panics.iter().rev() {
+      let backtrace = &panic.backtrace;
+      let message = summary(backtrace);
+      let located = backtrace
+        .entries()
+        .iter()
+        .filter_map(|entry| Some((entry, entry.location()?)))
+        .filter(|(_, location)| self.in_root(&location.path));
+
+      for (index, (entry, location)) in located.enumerate() {
+        let (severity, text) = match index {
+          0 => (
+            DiagnosticSeverity::ERROR,
+            format!("panicked here: {message}"),
+          ),
+          _ => (
+            DiagnosticSeverity::INFORMATION,
+            format!("{} on the way to: {message}", entry.function()),
+          ),
+        };
+        let diagnostic = diagnostic(location, severity, text);
+        files
+          .entry(location.path.clone())
+          .or_default()
+          .push(diagnostic);
+      }
+
+      for label in backtrace.labels() {
+        let location = &label.location;
+        let text = format!("{}: {message}", label.label);
+        let diagnostic = diagnostic(location, DiagnosticSeverity::HINT, text);
+        files
+          .entry(location.path.clone())
+          .or_default()
+          .push(diagnostic);
+      }
+    }
+    files
+  }
+
+  /// "panicked here" on the top frame of the panics in the file
+  fn lenses(&self, path: &str, now: SystemTime) -> Vec<CodeLens> {
+    let mut lenses = Vec::new();
+    for panic in &self.panics {
+      let backtrace = &panic.backtrace;
+      let top = backtrace
+        .entries()
+        .iter()
+        .filter_map(|entry| entry.location())
+        .find(|location| self.in_root(&location.path));
+      let Some(location) = top.filter(|location| location.path == path) else {
+        continue;
+      };
+
+      let elapsed = now.duration_since(panic.time).unwrap_or_default();
+      let position = Position::new(location.line.saturating_sub(1), 0);
+      lenses.push(CodeLens {
+        range: Range::new(position, position),
+        command: Some(lsp::Command {
+          title: format!(
+            "panicked here: {} ({})",
+            summary(backtrace),
+            ago(elapsed)
+          ),
+          command: CLEAR.to_string(),
+          arguments: Some(vec![Value::from(panic.fingerprint.clone())]),
+        }),
+        data: None,
+      });
+    }
+    lenses
+  }
+
+  fn publish(&mut self, connection: &Connection) {
+    let files = self.diagnostics();
+    let cleared: Vec<String> = self
+      .published
+      .iter()
+      .filter(|path| !files.contains_key(*path))
+      .cloned()
+      .collect();
+
+    let empty = cleared.into_iter().map(|path| (path, Vec::new()));
+    for (path, diagnostics) in files.clone().into_iter().chain(empty) {
+      let Some(uri) = path_uri(&path) else {
+        continue;
+      };
+      let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+      let notification =
+        Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+      let _ = connection.sender.send(notification.into());
+    }
+    self.published = files.into_keys().collect();
+
+    if self.refresh {
+      self.next_id += 1;
+      let request = Request::new(
+        RequestId::from(self.next_id),
+        "workspace/codeLens/refresh".to_string(),
+        (),
+      );
+      let _ = connection.sender.send(request.into());
+    }
+  }
+
+  fn request(&mut self, request: Request, connection: &Connection) {
+    let response = match request.method.as_str() {
+      CodeLensRequest::METHOD => {
+        match serde_json::from_value::<CodeLensParams>(request.params) {
+          Ok(params) => {
+            let path = uri_path(params.text_document.uri.as_str());
+            let lenses = path
+              .map(|path| self.lenses(&path, SystemTime::now()))
+              .unwrap_or_default();
+            Response::new_ok(request.id, lenses)
+          }
+          Err(e) => invalid(request.id, e),
+        }
+      }
+      ExecuteCommand::METHOD => {
+        match serde_json::from_value::<ExecuteCommandParams>(request.params) {
+          Ok(params) if params.command == CLEAR => {
+            match params.arguments.first().and_then(Value::as_str) {
+              Some(fingerprint) => {
+                self.panics.retain(|panic| panic.fingerprint != fingerprint)
+              }
+              None => self.panics.clear(),
+            }
+            self.publish(connection);
+            Response::new_ok(request.id, Value::Null)
+          }
+          Ok(params) => Response::new_err(
+            request.id,
+            ErrorCode::InvalidParams as i32,
+            format!("no command `{}`", params.command),
+          ),
+          Err(e) => invalid(request.id, e),
+        }
+      }
+      "shutdown" => Response::new_ok(request.id, Value::Null),
+      method => Response::new_err(
+        request.id,
+        ErrorCode::MethodNotFound as i32,
+        format!("stacky doesn't handle `{}`", method),
+      ),
+    };
+    let _ = connection.sender.send(response.into());
+  }
+}
+
+/// Empty range at the column, the client widens it to the word there
+fn diagnostic(
+  location: &Location,
+  severity: DiagnosticSeverity,
+  message: String,
+) -> lsp::Diagnostic {
+  let position = Position::new(
+    location.line.saturating_sub(1),
+    location.column.saturating_sub(1),
+  );
+  lsp::Diagnostic {
+    range: Range::new(position, position),
+    severity: Some(severity),
+    source: Some("stacky".to_string()),
+    message,
+    ..Default::default()
+  }
+}
+
+fn invalid(id: RequestId, error: serde_json::Error) -> Response {
+  Response::new_err(id, ErrorCode::InvalidParams as i32, error.to_string())
+}
+
+/// First line of the message, how many times it happened
+fn summary(backtrace: &Backtrace) -> String {
+  let message = backtrace.message().and_then(|m| m.lines().next());
+  let message = message.unwrap_or("panic");
+  match backtrace.count() {
+    1 => message.to_string(),
+    count => format!("{} ×{}", message, count),
+  }
+}
+
+fn ago(elapsed: Duration) -> String {
+  match elapsed.as_secs() {
+    0..10 => "just now".to_string(),
+    seconds @ 10..60 => format!("{} s ago", seconds),
+    seconds @ 60..3600 => format!("{} min ago", seconds / 60),
+    seconds => format!("{} h ago", seconds / 3600),
+  }
+}
+
+fn path_uri(path: &str) -> Option<Uri> {
+  format!("file://{}", encode_path(path)).parse().ok()
+}
+
+/// Path of a `file://` URI, percent-decoded
+fn uri_path(uri: &str) -> Option<String> {
+  let encoded = uri.strip_prefix("file://")?.as_bytes();
+  let mut path = Vec::with_capacity(encoded.len());
+  let mut index = 0;
+
+  while index < encoded.len() {
+    let hex = encoded.get(index + 1..index + 3);
+    let byte = hex
+      .filter(|_| encoded[index] == b'%')
+      .and_then(|hex| std::str::from_utf8(hex).ok())
+      .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    match byte {
+      Some(byte) => {
+        path.push(byte);
+        index += 3;
+      }
+      None => {
+        path.push(encoded[index]);
+        index += 1;
+      }
+    }
+  }
+  String::from_utf8(path).ok()
+}
+
+/// Speaks LSP on stdin and stdout until the client says exit
+pub fn serve() -> anyhow::Result<()> {
+  let (connection, io_threads) = Connection::stdio();
+
+  let capabilities = serde_json::to_value(ServerCapabilities {
+    code_lens_provider: Some(CodeLensOptions {
+      resolve_provider: Some(false),
+    }),
+    execute_command_provider: Some(ExecuteCommandOptions {
+      commands: vec![CLEAR.to_string()],
+      ..Default::default()
+    }),
+    ..Default::default()
+  })?;
+  let params = connection.initialize(capabilities)?;
+  let mut server = Server::new(&params);
+
+  let socket = runtime_dir()?.join(format!("lsp-{}", std::process::id()));
+  let _ = fs::remove_file(&socket);
+  let listener = UnixListener::bind(&socket)?;
+
+  let (events, received) = mpsc::channel();
+
+  let messages = connection.receiver.clone();
+  let sender = events.clone();
+  thread::spawn(move || {
+    for message in messages {
+      if sender.send(Event::Message(message)).is_err() {
+        break;
+      }
+    }
+  });
+
+  thread::spawn(move || {
+    for stream in listener.incoming().filter_map(Result::ok) {
+      let reports = BufReader::new(stream)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok());
+      for report in reports {
+        if events.send(Event::Report(report)).is_err() {
+          return;
+        }
+      }
+    }
+  });
+
+  for event in received {
+    match event {
+      Event::Message(Message::Request(request)) => {
+        server.request(request, &connection)
+      }
+      Event::Message(Message::Notification(notification))
+        if notification.method == "exit" =>
+      {
+        break;
+      }
+      Event::Message(_) => {}
+      Event::Report(report) => server.report(report, &connection),
+    }
+  }
+
+  let _ = fs::remove_file(&socket);
+  drop(connection);
+  io_threads.join()?;
+  Ok(())
+}
+
+#[test]
+fn lsp_test() {
+  use crate::types::Entry;
+
+  let location = |path: &str, line| Location {
+    path: path.to_string(),
+    line,
+    column: 5,
+  };
+  let mut backtrace = Backtrace::with_entries(vec![
+    Entry::new(
+      "app::run".to_string(),
+      Some(location("/home/user/my app/src/run.rs", 42)),
+    ),
+    Entry::new(
+      "app::main".to_string(),
+      Some(location("/home/user/my app/src/main.rs", 8)),
+    ),
+  ])
+  .with_message("boom".to_string());
+
+  let params = serde_json::json!({
+    "rootUri": "file:///home/user/my%20app",
+    "capabilities": {},
+  });
+  let mut server = Server::new(&params);
+  assert_eq!(server.root.as_deref(), Some("/home/user/my app"));
+
+  let time = SystemTime::UNIX_EPOCH;
+  let fingerprint = backtrace.fingerprint().to_string();
+  server.panics.push(Panic {
+    backtrace,
+    fingerprint,
+    time,
+  });
+
+  let files = server.diagnostics();
+  let run = &files["/home/user/my app/src/run.rs"];
+  assert_eq!(run[0].message, "panicked here: boom");
+  assert_eq!(run[0].range.start, Position::new(41, 4));
+  assert_eq!(files.len(), 2);
+
+  let now = time + Duration::from_secs(180);
+  let lenses = server.lenses("/home/user/my app/src/run.rs", now);
+  let command = lenses[0].command.as_ref().unwrap();
+  assert_eq!(command.title, "panicked here: boom (3 min ago)");
+  assert!(server
+    .lenses("/home/user/my app/src/main.rs", now)
+    .is_empty());
+
+  let uri = path_uri("/home/user/my app/src/run.rs").unwrap();
+  assert_eq!(uri.as_str(), "file:///home/user/my%20app/src/run.rs");
+}
@@ -1,156 +1,758 @@
 mod config;
-mod parser3;
+mod context;
+mod daemon;
+mod dedup;
+mod diff;
+mod emacs;
+mod eyre;
+mod format;
+mod gdb;
+mod history;
+mod html;
+mod issue;
+mod lldb;
+mod lsp;
+mod miri;
+mod output;
+mod rust;
+mod rustc;
+mod sarif;
+mod sink;
+mod symbolize;
+mod tui;
 mod types;
 mod utils;
 
-use config::Config;
-use nvim_rs::{create::tokio::new_path, rpc::handler::Dummy};
-use parser3::parse_backtrace;
-use std::io::Write;
+use config::{Command, Config, HistoryCommand};
+use context::Context;
+use daemon::{Message, Producer};
+use dedup::Dedup;
+use format::{Capture, Registry};
+use history::History;
+use html::Html;
+use nvim_rs::{create::tokio::new_path, rpc::handler::Dummy, Value};
+use sarif::Sarif;
+use sink::Sink;
+use std::{
+  env,
+  io::Write,
+  path::Path,
+  time::{Duration, Instant},
+};
+use symbolize::Symbolizer;
 use tokio::{
   io::{self, AsyncBufReadExt},
-  sync::mpsc::{unbounded_channel, UnboundedReceiver},
+  sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+  task,
 };
-use types::Backtrace;
-use utils::get_nvim_pipes;
+use types::{Backtrace, Diagnostic, Report};
+use utils::{get_nvim_pipes, set_style, Style};
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-  // This will eventually come from somewhere if there's a need
-  let config = config::Config::default();
+  let args = Config::project_args()
+    .into_iter()
+    .chain(env::args().skip(1));
+  let config = Config::from_args(args)?;
+  let color = config.color.enabled();
+  set_style(Style {
+    color,
+    links: color.then(|| config.editor_uri.clone()),
+  });
+  match &config.command {
+    Command::History(command) => return history(*command, &config).await,
+    Command::Lsp => return Ok(lsp::serve()?),
+    Command::Daemon => return daemon(&config).await,
+    Command::Trust => {
+      let path = Config::trust()?;
+      eprintln!("Stacky: trusting {}", path.display());
+      return Ok(());
+    }
+    Command::Diff(a, b) => {
+      let history = History::open(&config).ok();
+      let a = diff::load(a, history.as_ref())?;
+      let b = diff::load(b, history.as_ref())?;
+      print!("{}", diff::Diff::new(&a, &b));
+      return Ok(());
+    }
+    _ => {}
+  }
+
+  let registry = Registry::new(&config);
+  let mut session = Session {
+    symbolizer: Symbolizer::new(&config),
+    context: Context::default(),
+    sarif: config.sarif.as_ref().map(|_| Sarif::default()),
+    html: config.html.as_ref().map(|_| Html::default()),
+    history: match config.history {
+      true => History::open(&config)
+        .inspect_err(|e| eprintln!("Stacky history disabled: {}", e))
+        .ok(),
+      false => None,
+    },
+    dedup: (config.dedup_window > 0)
+      .then(|| Dedup::new(Duration::from_secs(config.dedup_window))),
+    tui: config.tui.then(Vec::new),
+    report: None,
+    daemon: Daemon::connect(&config),
+  };
+  if let (Some(daemon), true) = (&session.daemon, config.verbose) {
+    eprintln!("Stacky: DELIVERING THROUGH THE DAEMON");
+    if let Some((_, sinks)) = &daemon.local {
+      eprintln!("Stacky: DELIVERING TO {:?} FROM HERE", sinks);
+    }
+  }
 
   let stdin = io::stdin();
-  let mut stdout = std::io::stdout();
+  // JSON on stdout stays parseable, the rest of the input goes to stderr
+  let mut passthrough: Box<dyn Write> = match config.reserves_stdout() {
+    true => Box::new(std::io::stderr()),
+    false => Box::new(std::io::stdout()),
+  };
   let stdin_buf = io::BufReader::new(stdin);
   let mut lines = stdin_buf.lines();
 
-  let mut append = false;
-  let mut full = String::new();
+  let mut capture: Option<Capture> = None;
+  let mut diagnostics: Option<String> = None;
+
+  let (rx, tx) = unbounded_channel::<Report>();
 
-  let (rx, tx) = unbounded_channel::<Backtrace>();
+  // task notifying nvim and the other sinks
+  let nvim = tokio::spawn(deliver_task(tx, config.clone()));
 
-  // task notifying nvim
-  tokio::spawn(nvim_task(tx, config.clone()));
+  // Counters of repeating backtraces go out while the input is quiet too
+  let mut throttle = tokio::time::interval(dedup::THROTTLE);
 
   // Main task reading stdin line by line
-  while let Ok(Some(line)) = lines.next_line().await {
-    if append {
-      // We suppress the output for a trace
-      full.push_str(&line);
-      full.push('\n');
+  loop {
+    let line = tokio::select! {
+      line = lines.next_line() => match line {
+        Ok(Some(line)) => line,
+        _ => break,
+      },
+      _ = throttle.tick() => {
+        if let Some(dedup) = &mut session.dedup {
+          let repeats = dedup.due(Instant::now());
+          send_repeats(repeats, &config, &mut session.daemon, &rx);
+        }
+        continue;
+      }
+    };
+
+    if let Some(ended) = capture.take_if(|c| !c.format().is_continuation(&line))
+    {
       if config.verbose {
-        println!("Stacky: APPENDING LINE {}", line);
+        eprintln!("Stacky: STOP APPENDING");
       }
+      let (parsed, raw) = ended.finish();
+      finish(parsed, &raw, &config, &mut session, &rx);
     }
 
     // The beginning of backtrace
-    // Not sure why 2 different string
-    if line.contains("stack backtrace:") || line.contains("Stack backtrace:") {
-      if config.verbose {
-        println!("Stacky: START APPENDING");
+    if capture.is_none() {
+      capture = registry.detect(&line).map(Capture::new);
+      if let (Some(capture), true) = (&capture, config.verbose) {
+        eprintln!("Stacky: START APPENDING {}", capture.format().name());
       }
-      append = true;
     }
 
-    if !append {
+    let Some(current) = &mut capture else {
+      // The test and the panic that the next backtrace belongs to
+      session.context.push_line(&line);
+
+      // Compiler output is collected on the side, it's still printed as is.
+      // A line that isn't a part of it, like a log line of the program that
+      // started with `error:`, ends it
+      if let Some(collected) =
+        diagnostics.take_if(|_| !rustc::is_continuation(&line))
+      {
+        finish_diagnostics(&collected, &mut session.daemon, &rx);
+      }
+      if diagnostics.is_none() && rustc::is_start(&line) {
+        diagnostics = Some(String::new());
+      }
+
+      if let Some(collected) = &mut diagnostics {
+        collected.push_str(&line);
+        collected.push('\n');
+
+        if rustc::is_end(&line) || collected.len() > rustc::LIMIT {
+          finish_diagnostics(collected, &mut session.daemon, &rx);
+          diagnostics = None;
+        }
+      }
+
       // could be an option to dump backtrace, but probably we only want a short version or none
       // if it's sent to neovim
-      stdout.write_all(format!("{}\n", line).as_bytes())?;
+      passthrough.write_all(format!("{}\n", line).as_bytes())?;
       continue;
+    };
+
+    // We suppress the output for a trace
+    current.push_line(&line);
+    if config.verbose {
+      eprintln!("Stacky: APPENDING LINE {}", line);
     }
 
-    // This looks like the end of backtrace that I'm interested in
-    if line.contains("0x0 - ")
-      || line.ends_with(" - _start")
-      || line.ends_with(" - _main") // osx ?
-      || line.ends_with(": _start")
-    {
+    if current.format().is_end(&line) {
       if config.verbose {
-        println!("Stacky: STOP APPENDING");
+        eprintln!("Stacky: STOP APPENDING");
+      }
+      if let Some(ended) = capture.take() {
+        let (parsed, raw) = ended.finish();
+        finish(parsed, &raw, &config, &mut session, &rx);
       }
-      append = false;
+    }
+  }
 
-      let backtrace = parse_backtrace(full.as_str());
+  // `gdb -batch` and friends end their dump with the stream
+  if let Some(ended) = capture {
+    let (parsed, raw) = ended.finish();
+    finish(parsed, &raw, &config, &mut session, &rx);
+  }
+  if let Some(dedup) = &mut session.dedup {
+    send_repeats(dedup.rest(), &config, &mut session.daemon, &rx);
+  }
+  if let Some(collected) = diagnostics {
+    finish_diagnostics(&collected, &mut session.daemon, &rx);
+  }
+  if let (Some(sarif), Some(path)) = (&session.sarif, &config.sarif) {
+    if let Err(e) = sarif.write(path) {
+      eprintln!("Stacky error writing {}: {}", path.display(), e);
+    }
+  }
+  if let (Some(html), Some(path)) = (&session.html, &config.html) {
+    if let Err(e) = html.write(path) {
+      eprintln!("Stacky error writing {}: {}", path.display(), e);
+    }
+  }
+  if config.command == Command::Report {
+    match (&session.report, env::current_dir()) {
+      (Some((backtrace, full_trace)), Ok(cwd)) => {
+        // Only once, it runs rustc and git
+        let environment = issue::Environment::detect(&cwd);
+        print!("{}", issue::render(backtrace, full_trace, &environment));
+      }
+      _ => eprintln!("Stacky: no backtrace to report"),
+    }
+  }
 
-      match backtrace {
-        Ok((_, mut backtrace)) => {
-          backtrace.filter();
-          // Print the short backtrace
-          println!("{}", backtrace);
+  // Let the delivery tasks deliver what's left
+  drop(rx);
+  nvim.await?;
+  if let Some(local) = session.daemon.and_then(Daemon::close) {
+    local.await?;
+  }
 
-          if let Err(e) = rx.send(backtrace) {
-            eprintln!(
-              "Stacky error sending backtrace through a channel: {}",
-              e
-            );
-          }
+  // A clean run has nothing to browse and exits as usual
+  if let Some(backtraces) = session.tui.filter(|b| !b.is_empty()) {
+    tui::run(backtraces)?;
+  }
 
-          full.clear();
-        }
-        Err(err) => {
-          println!("--- BACKTRACE PARSE ERROR ------------------------");
+  Ok(())
+}
 
-          if config.verbose {
-            eprintln!("{:?}", err);
-          }
+/// What the backtraces of the whole input are collected into
+struct Session {
+  symbolizer: Symbolizer,
+  context: Context,
+  sarif: Option<Sarif>,
+  html: Option<Html>,
+  history: Option<History>,
+  dedup: Option<Dedup>,
+  /// Unfiltered backtraces for `--tui`
+  tui: Option<Vec<Backtrace>>,
+  /// The last backtrace and its full trace for `stacky report`
+  report: Option<(Backtrace, String)>,
+  /// `stacky daemon` stores, counts and delivers the backtraces when it runs,
+  /// the terminal still gets them from here
+  daemon: Option<Daemon>,
+}
 
-          full.clear();
-          continue;
-        }
-      };
+/// The connection to the daemon
+struct Daemon {
+  producer: Producer,
+  /// Delivers to the sinks the daemon doesn't have
+  local: Option<(UnboundedSender<Report>, Vec<Sink>)>,
+  task: Option<task::JoinHandle<()>>,
+}
+
+impl Daemon {
+  fn connect(config: &Config) -> Option<Self> {
+    let producer = Producer::connect(config)?;
+    let sinks: Vec<Sink> = config
+      .sinks
+      .iter()
+      .filter(|sink| !producer.sinks().contains(sink))
+      .cloned()
+      .collect();
+    if sinks.is_empty() {
+      return Some(Self {
+        producer,
+        local: None,
+        task: None,
+      });
     }
+
+    let (rx, tx) = unbounded_channel::<Report>();
+    let config = Config {
+      sinks: sinks.clone(),
+      ..config.clone()
+    };
+    Some(Self {
+      producer,
+      local: Some((rx, sinks)),
+      task: Some(tokio::spawn(deliver_task(tx, config))),
+    })
   }
 
-  Ok(())
+  /// Hangs up, the task delivering to the other sinks ends once it has
+  /// delivered the rest
+  fn close(self) -> Option<task::JoinHandle<()>> {
+    self.task
+  }
+}
+
+/// The daemon delivers what it got to its own sinks, the others get it from
+/// here. Without a daemon all of them get it from here
+fn deliver(
+  report: Report,
+  forwarded: bool,
+  daemon: &Option<Daemon>,
+  rx: &UnboundedSender<Report>,
+) {
+  let rx = match (forwarded, daemon) {
+    (false, _) => rx,
+    (
+      true,
+      Some(Daemon {
+        local: Some((local, _)),
+        ..
+      }),
+    ) => local,
+    (true, _) => return,
+  };
+  if let Err(e) = rx.send(report) {
+    eprintln!("Stacky error sending a report through a channel: {}", e);
+  }
 }
 
-async fn nvim_task(
-  mut backtraces: UnboundedReceiver<Backtrace>,
-  config: Config,
+/// Hands the message to the daemon, `false` when there's none or it's gone
+fn forward(
+  daemon: &mut Option<Daemon>,
+  message: impl FnOnce(&mut Producer) -> Message,
+) -> bool {
+  let Some(Daemon { producer, .. }) = daemon else {
+    return false;
+  };
+  let message = message(producer);
+  match producer.send(&message) {
+    Ok(()) => true,
+    Err(e) => {
+      eprintln!(
+        "Stacky error sending to the daemon, delivering directly: {}",
+        e
+      );
+      *daemon = None;
+      false
+    }
+  }
+}
+
+/// Hands the parsed backtraces to the nvim task
+fn finish(
+  parsed: anyhow::Result<Vec<Backtrace>>,
+  raw: &str,
+  config: &Config,
+  session: &mut Session,
+  rx: &UnboundedSender<Report>,
 ) {
-  while let Some(backtrace) = backtraces.recv().await {
-    let pipes = get_nvim_pipes();
+  let backtraces = match parsed {
+    Ok(backtraces) => backtraces,
+    Err(err) => {
+      eprintln!("--- BACKTRACE PARSE ERROR ------------------------");
 
-    for pipe in pipes {
       if config.verbose {
-        println!("nvim pipe: {:?}", pipe);
+        eprintln!("{:?}", err);
       }
+      return;
+    }
+  };
 
-      let nvim = new_path(pipe.clone(), Dummy::new());
-      let Ok((writer, _join_handle)) = nvim.await else {
-        continue;
-      };
+  let cwd = env::current_dir();
 
-      let cwd = writer
-        .exec_lua("return vim.fn.getcwd()", vec![])
-        .await
-        .map(|val| val.as_str().unwrap_or("").to_owned())
-        .unwrap_or_else(|_e| {
-          eprintln!("Couldn't retrieve CWD");
-          "/".to_string()
-        });
+  for backtrace in backtraces {
+    let mut backtrace = session.context.apply(backtrace);
+    if session.symbolizer.is_enabled() {
+      backtrace.symbolize(&mut session.symbolizer);
+    }
+    if !config.keep_hash {
+      backtrace.strip_symbols();
+    }
+    if config.collapse_runtime {
+      backtrace.collapse_runtime();
+    }
+    backtrace.group_inlined();
 
-      // Is any entry matching a CWD of neovim instance
-      // We only need to send a backgrace to those that match
-      let has_location = backtrace.has_location(&cwd);
+    let now = Instant::now();
+    let fingerprint = backtrace.fingerprint().to_string();
+    let dedup = session.dedup.as_mut();
+    if let Some(count) = dedup.and_then(|d| d.repeat(&fingerprint, now)) {
+      if config.verbose {
+        eprintln!("Stacky: REPEAT {} ×{}", fingerprint, count);
+      }
+      continue;
+    }
+    // Repeats go as counters, see `send_repeats`
+    let forwarded = forward(&mut session.daemon, |daemon| {
+      daemon.backtrace(&fingerprint, &backtrace, raw)
+    });
 
-      let json =
-        serde_json::to_string(&backtrace).expect("Failed JSON serialize");
+    let full_trace = match &config.command {
+      Command::Report => issue::full_trace(&backtrace),
+      _ => String::new(),
+    };
+    // Stored unfiltered, `History::load` filters it again
+    if let (Some(history), false) = (&session.history, forwarded) {
+      if let Err(e) = history.save(&backtrace, raw) {
+        eprintln!("Stacky error saving backtrace to the history: {}", e);
+      }
+    }
+    if let (Some(html), Ok(cwd)) = (session.html.as_mut(), &cwd) {
+      html.push(&backtrace, cwd);
+    }
+    if let Some(browse) = &mut session.tui {
+      let mut full = backtrace.clone();
+      if let Ok(cwd) = &cwd {
+        full.resolve(cwd);
+      }
+      browse.push(full);
+    }
+    backtrace.filter();
+    if let Ok(cwd) = &cwd {
+      backtrace.resolve(cwd);
+    }
+    if let (true, Ok(cwd)) = (config.context_lines > 0, &cwd) {
+      backtrace.load_sources(cwd, config.context_lines);
+    }
+    if let (Some(sarif), Ok(cwd)) = (session.sarif.as_mut(), &cwd) {
+      sarif.push(&backtrace, cwd);
+    }
+    if config.command == Command::Report {
+      session.report = Some((backtrace.clone(), full_trace));
+    }
 
-      let stacky_function = &config.stacky_function;
-      let lua = format!("{stacky_function}('{json}')");
+    // Print the short backtrace
+    let rendered = output::render(&backtrace, config.format);
+    match &config.command {
+      Command::Report => eprint!("{}", rendered),
+      _ => print!("{}", rendered),
+    }
 
-      // Only send the backtrace if CWD is in it
-      if !has_location {
+    if let Some(dedup) = &mut session.dedup {
+      dedup.insert(fingerprint, backtrace.clone(), now);
+    }
+    deliver(Report::Backtrace(backtrace), forwarded, &session.daemon, rx);
+  }
+}
+
+/// Sends the new counters of repeating backtraces, the terminal gets a line
+/// instead of the whole backtrace again. The daemon adds them to its own
+fn send_repeats(
+  repeats: Vec<Backtrace>,
+  config: &Config,
+  daemon: &mut Option<Daemon>,
+  rx: &UnboundedSender<Report>,
+) {
+  for mut backtrace in repeats {
+    let rendered = match config.format.is_json() {
+      true => output::render(&backtrace, config.format),
+      false => {
+        let message = backtrace.message().and_then(|m| m.lines().next());
+        format!(
+          "Stacky: same panic ×{}: {}\n",
+          backtrace.count(),
+          message.unwrap_or("")
+        )
+      }
+    };
+    match &config.command {
+      Command::Report => eprint!("{}", rendered),
+      _ => print!("{}", rendered),
+    }
+
+    let forwarded = forward(daemon, |daemon| daemon.repeat(&mut backtrace));
+    deliver(Report::Backtrace(backtrace), forwarded, daemon, rx);
+  }
+}
+
+/// `stacky history`, nothing is read from stdin
+async fn history(
+  command: HistoryCommand,
+  config: &Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+  let history = History::open(config)?;
+  match command {
+    HistoryCommand::List => history.list()?,
+    HistoryCommand::Show(id) if config.tui => {
+      tui::run(vec![history.load_full(id)?.backtrace])?
+    }
+    HistoryCommand::Show(id) => history.show(id)?,
+    HistoryCommand::Resend(id) => {
+      let record = history.load(id)?;
+      let (rx, tx) = unbounded_channel::<Report>();
+      let nvim = tokio::spawn(deliver_task(tx, config.clone()));
+      rx.send(Report::Backtrace(record.backtrace))?;
+      drop(rx);
+      nvim.await?;
+    }
+  }
+  Ok(())
+}
+
+/// `stacky daemon`, stores, counts and delivers what the piped stacky
+/// processes parsed until it's killed
+async fn daemon(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+  let mut messages = daemon::listen(&config.sinks)?;
+  let history = match config.history {
+    true => History::open(config)
+      .inspect_err(|e| eprintln!("Stacky history disabled: {}", e))
+      .ok(),
+    false => None,
+  };
+  let mut dedup = (config.dedup_window > 0)
+    .then(|| Dedup::new(Duration::from_secs(config.dedup_window)));
+  let window = Duration::from_secs(config.dedup_window);
+  let mut late = daemon::Late::new(window);
+
+  let (rx, tx) = unbounded_channel::<Report>();
+  let nvim = tokio::spawn(deliver_task(tx, config.clone()));
+  let mut throttle = tokio::time::interval(dedup::THROTTLE);
+  let deliver = |report: Report, late: &mut daemon::Late| {
+    late.push(report.clone(), Instant::now());
+    if let Err(e) = rx.send(report) {
+      eprintln!("Stacky error sending backtrace through a channel: {}", e);
+    }
+  };
+
+  loop {
+    let message = tokio::select! {
+      message = messages.recv() => match message {
+        Some(message) => message,
+        None => break,
+      },
+      _ = throttle.tick() => {
+        let repeats = dedup.as_mut().map(|d| d.due(Instant::now()));
+        for backtrace in repeats.unwrap_or_default() {
+          deliver(Report::Backtrace(backtrace), &mut late);
+        }
+
+        // Neovim started after the backtraces were delivered
+        if config.sinks.contains(&Sink::Nvim) {
+          for pipe in late.started() {
+            for report in late.reports(Instant::now()) {
+              deliver_nvim_pipe(&pipe, report, config).await;
+            }
+          }
+        }
+        continue;
+      }
+    };
+
+    let (origin, raw, mut backtrace, keep) = match message {
+      Message::Backtrace {
+        origin,
+        raw,
+        backtrace,
+        history,
+      } => (origin, raw, *backtrace, history),
+      Message::Repeat { fingerprint, count } => {
+        let dedup = dedup.as_mut();
+        let total =
+          dedup.and_then(|d| d.repeat_by(&fingerprint, count, Instant::now()));
+        if let (Some(total), true) = (total, config.verbose) {
+          eprintln!("Stacky: REPEAT {} ×{}", fingerprint, total);
+        }
         continue;
       }
+      Message::Diagnostics(diagnostics) => {
+        deliver(Report::Diagnostics(diagnostics), &mut late);
+        continue;
+      }
+    };
+
+    let now = Instant::now();
+    let fingerprint = backtrace.fingerprint().to_string();
+    let repeat = dedup.as_mut().and_then(|d| d.repeat(&fingerprint, now));
+    if let Some(count) = repeat {
+      if config.verbose {
+        eprintln!("Stacky: REPEAT {} ×{}", fingerprint, count);
+      }
+      continue;
+    }
+
+    // The producer was run with `--no-history`
+    if let (Some(history), true) = (&history, keep) {
+      if let Err(e) = history.save_from(&origin, &backtrace, &raw) {
+        eprintln!("Stacky error saving backtrace to the history: {}", e);
+      }
+    }
+    backtrace.filter();
+    backtrace.resolve(Path::new(&origin.cwd));
+    if config.context_lines > 0 {
+      backtrace.load_sources(Path::new(&origin.cwd), config.context_lines);
+    }
+    if config.verbose {
+      eprintln!("Stacky: FROM {}", origin.cwd);
+    }
 
-      if let Err(e) = writer.exec_lua(&lua, vec![]).await {
-        eprintln!(
-          "Stacky error sending backtrace to Neovim instance '{:?}': {}",
-          pipe, e
-        );
+    if let Some(dedup) = &mut dedup {
+      dedup.insert(fingerprint, backtrace.clone(), now);
+    }
+    deliver(Report::Backtrace(backtrace), &mut late);
+  }
+
+  drop(rx);
+  nvim.await?;
+  Ok(())
+}
+
+/// Parses the collected compiler output and hands it to the nvim task
+fn finish_diagnostics(
+  collected: &str,
+  daemon: &mut Option<Daemon>,
+  rx: &UnboundedSender<Report>,
+) {
+  let mut diagnostics: Vec<Diagnostic> = rustc::parse_diagnostics(collected);
+  if diagnostics.is_empty() {
+    return;
+  }
+
+  // Paths are relative to the workspace cargo was run in
+  if let Ok(cwd) = env::current_dir() {
+    for diagnostic in &mut diagnostics {
+      diagnostic.resolve(&cwd);
+    }
+  }
+
+  let forwarded =
+    forward(daemon, |_| Message::Diagnostics(diagnostics.clone()));
+  deliver(Report::Diagnostics(diagnostics), forwarded, daemon, rx);
+}
+
+async fn deliver_task(mut reports: UnboundedReceiver<Report>, config: Config) {
+  // The passthrough would write over a terminal editor, they get the last
+  // report once the input has ended
+  let mut last: Vec<(&Sink, Report)> = Vec::new();
+
+  while let Some(report) = reports.recv().await {
+    for sink in &config.sinks {
+      match sink {
+        Sink::Nvim => deliver_nvim(&report, &config).await,
+        Sink::Command(template) if template.is_terminal() => {
+          if !sink::is_repeat(&report) {
+            last.retain(|(terminal, _)| *terminal != sink);
+            last.push((sink, report.clone()));
+          }
+        }
+        sink => deliver_elsewhere(sink, &report, &config).await,
       }
     }
   }
+
+  for (sink, report) in last {
+    deliver_elsewhere(sink, &report, &config).await;
+  }
+}
+
+/// emacsclient, kak and the commands block until they're done, the input
+/// keeps being passed through meanwhile
+async fn deliver_elsewhere(sink: &Sink, report: &Report, config: &Config) {
+  let (sink, report, config) = (sink.clone(), report.clone(), config.clone());
+  let delivered =
+    task::spawn_blocking(move || deliver_blocking(&sink, &report, &config));
+  if let Err(e) = delivered.await {
+    eprintln!("Stacky error delivering a report: {}", e);
+  }
+}
+
+fn deliver_blocking(sink: &Sink, report: &Report, config: &Config) {
+  match sink {
+    Sink::Nvim => {}
+    Sink::Emacs => emacs::deliver(report, config.verbose),
+    Sink::Lsp => lsp::deliver(report, config.verbose),
+    Sink::Kak => sink::kak(
+      report,
+      &config.kak_session,
+      &config.kak_client,
+      config.verbose,
+    ),
+    Sink::Command(template) => template.run(report, config.verbose),
+  }
+}
+
+async fn deliver_nvim(report: &Report, config: &Config) {
+  for pipe in get_nvim_pipes() {
+    deliver_nvim_pipe(&pipe, report, config).await;
+  }
+}
+
+async fn deliver_nvim_pipe(pipe: &Path, report: &Report, config: &Config) {
+  if config.verbose {
+    eprintln!("nvim pipe: {:?}", pipe);
+  }
+
+  let nvim = new_path(pipe, Dummy::new());
+  let Ok((writer, _join_handle)) = nvim.await else {
+    return;
+  };
+
+  let cwd = writer
+    .exec_lua("return vim.fn.getcwd()", vec![])
+    .await
+    .map(|val| val.as_str().unwrap_or("").to_owned())
+    .unwrap_or_else(|_e| {
+      eprintln!("Couldn't retrieve CWD");
+      "/".to_string()
+    });
+
+  // Is any entry matching a CWD of neovim instance
+  // We only need to send a backgrace to those that match
+  let has_location = report.has_location(&cwd);
+
+  // JSON goes in as arguments, compiler messages are full of quotes
+  let (lua, args) = match report {
+    Report::Backtrace(backtrace) => (
+      format!(
+        "local object, entries = ...
+        if {0} then return {0}(object) end
+        return {1}(entries)",
+        config.backtrace_function, config.stacky_function
+      ),
+      vec![
+        serde_json::to_string(backtrace),
+        serde_json::to_string(backtrace.entries()),
+      ],
+    ),
+    Report::Diagnostics(diagnostics) => (
+      format!("{}(...)", config.diagnostics_function),
+      vec![serde_json::to_string(diagnostics)],
+    ),
+  };
+  let args = args
+    .into_iter()
+    .map(|json| Value::from(json.expect("Failed JSON serialize")))
+    .collect();
+
+  // Only send the backtrace if CWD is in it
+  if !has_location {
+    return;
+  }
+
+  if let Err(e) = writer.exec_lua(&lua, args).await {
+    eprintln!(
+      "Stacky error sending backtrace to Neovim instance '{:?}': {}",
+      pipe, e
+    );
+  }
 }
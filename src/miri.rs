@@ -0,0 +1,170 @@
+//! Parser for Miri undefined behavior reports
+//!
+//! error: Undefined Behavior: attempting a read access using <2584> ...
+//!    = note: inside `foo` at src/main.rs:6:20: 6:24
+use crate::format::BacktraceFormat;
+use crate::types::{Backtrace, Entry, Label, Location};
+use nom::{
+  branch::alt,
+  bytes::complete::{tag, take_until},
+  character::complete::{char, digit1, space0},
+  combinator::map_res,
+  sequence::{delimited, preceded, tuple},
+  IResult,
+};
+
+pub struct Miri;
+
+impl BacktraceFormat for Miri {
+  fn name(&self) -> &'static str {
+    "miri"
+  }
+
+  fn is_start(&self, line: &str) -> bool {
+    line.starts_with("error: Undefined Behavior: ")
+  }
+
+  /// The report is a rustc diagnostic, it lasts until the next unindented
+  /// line that isn't one of its `note:` or `help:` parts or a quoted source
+  fn is_continuation(&self, line: &str) -> bool {
+    let source = line.trim_start_matches(|c: char| c.is_ascii_digit());
+
+    line.is_empty()
+      || line.starts_with(char::is_whitespace)
+      || source.trim_start().starts_with('|')
+      || line.starts_with("note: ")
+      || line.starts_with("help: ")
+      || line.starts_with("error: aborting due to")
+  }
+
+  fn parse(&self, i: &str) -> anyhow::Result<Vec<Backtrace>> {
+    Ok(parse_backtrace(i).into_iter().collect())
+  }
+}
+
+/// Who the ` --> ` span on the next line belongs to
+enum Span {
+  Primary,
+  Label(String),
+  Frame(String),
+}
+
+/// The "inside ... at" chain becomes the backtrace, the Stacked Borrows and
+/// Tree Borrows tag history becomes its labels
+fn parse_backtrace(i: &str) -> Option<Backtrace> {
+  let mut message = None;
+  let mut primary = None;
+  let mut entries = Vec::new();
+  let mut labels = Vec::new();
+  let mut span = None;
+
+  for line in i.lines() {
+    // Spans directly follow the header they belong to
+    let pending = span.take();
+
+    if let Ok((_, location)) = parse_span(line) {
+      match pending {
+        Some(Span::Primary) => primary = Some(location),
+        Some(Span::Label(label)) => labels.push(Label { label, location }),
+        Some(Span::Frame(function)) => {
+          entries.push(Entry::new(function, Some(location)))
+        }
+        None => {}
+      }
+    } else if let Ok((_, (function, location))) = parse_inside(line) {
+      entries.push(Entry::new(function.to_string(), Some(location)));
+    } else if let Ok((_, function)) = parse_inside_header(line) {
+      span = Some(Span::Frame(function.to_string()));
+    } else if Miri.is_start(line) {
+      message = line.strip_prefix("error: ").map(str::to_string);
+      span = Some(Span::Primary);
+    } else if let Some(label) = line
+      .strip_prefix("help: ")
+      .or_else(|| line.strip_prefix("note: "))
+    {
+      span = Some(Span::Label(label.to_string()));
+    }
+  }
+
+  // Without the backtrace note the primary span is the only frame we know
+  if entries.is_empty() {
+    entries.push(Entry::new("<unknown>".to_string(), primary));
+  }
+
+  let backtrace = Backtrace::with_entries(entries)
+    .with_message(message?)
+    .with_labels(labels);
+  Some(backtrace)
+}
+
+/// src/main.rs:6:20: 6:24, the end of the range is ignored
+fn parse_location(i: &str) -> IResult<&str, Location> {
+  let (i, (path, _, line, _, column)) = tuple((
+    take_until(":"),
+    char(':'),
+    map_res(digit1, str::parse::<u32>),
+    char(':'),
+    map_res(digit1, str::parse::<u32>),
+  ))(i)?;
+
+  let location = Location {
+    path: path.to_string(),
+    line,
+    column,
+  };
+  Ok((i, location))
+}
+
+fn parse_span(i: &str) -> IResult<&str, Location> {
+  preceded(tuple((space0, tag("--> "))), parse_location)(i)
+}
+
+fn parse_function(i: &str) -> IResult<&str, &str> {
+  alt((
+    delimited(char('`'), take_until("`"), char('`')),
+    tag("closure"),
+  ))(i)
+}
+
+///    = note: inside `foo` at src/main.rs:6:20: 6:24
+fn parse_inside(i: &str) -> IResult<&str, (&str, Location)> {
+  let (i, (_, _, function, _, location)) = tuple((
+    space0,
+    tag("= note: inside "),
+    parse_function,
+    tag(" at "),
+    parse_location,
+  ))(i)?;
+  Ok((i, (function, location)))
+}
+
+/// note: inside `main`
+fn parse_inside_header(i: &str) -> IResult<&str, &str> {
+  preceded(tag("note: inside "), parse_function)(i)
+}
+
+#[test]
+fn miri_inside_parser_test() {
+  let input = "   = note: inside `foo` at src/main.rs:6:20: 6:24";
+  let (_i, (function, location)) = parse_inside(input).unwrap();
+  assert_eq!(function, "foo");
+  assert_eq!(location.to_string(), "src/main.rs:6:20");
+
+  let input = "   = note: inside closure at src/main.rs:9:13";
+  let (_i, (function, _location)) = parse_inside(input).unwrap();
+  assert_eq!(function, "closure");
+}
+
+#[test]
+fn miri_full_parser_test() {
+  let input = include_str!("./tests/fixtures/miri.txt");
+  assert!(input.lines().skip(1).all(|line| Miri.is_continuation(line)));
+
+  let backtrace = parse_backtrace(input).unwrap();
+  assert_eq!(backtrace.entries().len(), 3);
+
+  let labels = backtrace.labels();
+  assert_eq!(labels.len(), 2);
+  assert_eq!(labels[1].location.to_string(), "src/main.rs:5:13");
+  assert!(labels[1].label.contains("invalidated"));
+}
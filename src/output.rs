@@ -0,0 +1,166 @@
+//! What gets printed for a backtrace, `--format`
+use crate::types::{Backtrace, Entry};
+use serde_derive::Serialize;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+  /// Colored frames between the START and END banners
+  Pretty,
+  /// One JSON document per backtrace
+  Json,
+  /// One JSON line per frame
+  Jsonl,
+  /// `file:line:col: message` lines for `:cfile` and compilation-mode
+  Errorformat,
+  /// One line per backtrace
+  Short,
+}
+
+impl OutputFormat {
+  pub fn parse(format: &str) -> anyhow::Result<Self> {
+    match format {
+      "pretty" | "full" => Ok(Self::Pretty),
+      "json" => Ok(Self::Json),
+      "jsonl" => Ok(Self::Jsonl),
+      "errorformat" => Ok(Self::Errorformat),
+      "short" => Ok(Self::Short),
+      _ => anyhow::bail!(
+        "`--format` is pretty, json, jsonl, errorformat or short, not `{format}`"
+      ),
+    }
+  }
+
+  pub fn is_json(self) -> bool {
+    matches!(self, Self::Json | Self::Jsonl)
+  }
+}
+
+/// A frame of `jsonl` with the backtrace it belongs to
+#[derive(Serialize)]
+struct Frame<'a> {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  test: Option<&'a str>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  thread: Option<&'a str>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  message: Option<&'a str>,
+  #[serde(flatten)]
+  entry: &'a Entry,
+}
+
+/// The lines to print, each of them ends with a newline
+pub fn render(backtrace: &Backtrace, format: OutputFormat) -> String {
+  match format {
+    OutputFormat::Pretty => format!("{}\n", backtrace),
+    OutputFormat::Json => json_line(backtrace),
+    OutputFormat::Jsonl => backtrace
+      .entries()
+      .iter()
+      .map(|entry| {
+        json_line(&Frame {
+          test: backtrace.test(),
+          thread: backtrace.thread(),
+          message: backtrace.message(),
+          entry,
+        })
+      })
+      .collect(),
+    OutputFormat::Errorformat => errorformat(backtrace),
+    OutputFormat::Short => short(backtrace),
+  }
+}
+
+fn json_line(value: &impl serde::Serialize) -> String {
+  match serde_json::to_string(value) {
+    Ok(json) => format!("{}\n", json),
+    Err(err) => format!("{{\"error\": \"{}\"}}\n", err),
+  }
+}
+
+/// The panic message goes with the first frame, the labels follow the frames
+fn errorformat(backtrace: &Backtrace) -> String {
+  let mut message = backtrace.message().map(one_line);
+  let mut lines = String::new();
+
+  for entry in backtrace.entries() {
+    let Some(location) = entry.location() else {
+      continue;
+    };
+    let line = match message.take() {
+      Some(message) => {
+        format!("{}: {} in {}\n", location, message, entry.function())
+      }
+      None => format!("{}: {}\n", location, entry.function()),
+    };
+    lines.push_str(&line);
+  }
+
+  for label in backtrace.labels() {
+    lines.push_str(&format!("{}: {}\n", label.location, label.label));
+  }
+  lines
+}
+
+/// Every line of the output is an entry of its own for `errorformat`, so the
+/// lines of the message are joined
+fn one_line(message: &str) -> String {
+  let lines: Vec<&str> = message
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.is_empty())
+    .collect();
+  lines.join(" ")
+}
+
+/// `test: message at path:line:col in function (N frames)`
+fn short(backtrace: &Backtrace) -> String {
+  let mut line = String::new();
+  if let Some(test) = backtrace.test() {
+    line.push_str(&format!("{}: ", test));
+  }
+  line.push_str(&one_line(backtrace.message().unwrap_or("backtrace")));
+
+  let top = backtrace
+    .entries()
+    .iter()
+    .find_map(|entry| Some((entry.location()?, entry.function())));
+  if let Some((location, function)) = top {
+    line.push_str(&format!(" at {} in {}", location, function));
+  }
+
+  line.push_str(&format!(" ({} frames)\n", backtrace.entries().len()));
+  line
+}
+
+#[test]
+fn errorformat_test() {
+  use crate::{format::BacktraceFormat, lldb::Lldb};
+
+  let input = include_str!("./tests/fixtures/lldb.txt");
+  let backtraces = Lldb.parse(input).unwrap();
+  let backtrace = backtraces
+    .into_iter()
+    .next()
+    .unwrap()
+    .with_message("boom".to_string());
+
+  assert_eq!(
+    render(&backtrace, OutputFormat::Errorformat),
+    "worker.rs:42:5: boom in myapp::worker::run\n\
+     function.rs:250:5: core::ops::function::FnOnce::call_once\n\
+     main.rs:8:0: myapp::main\n"
+  );
+
+  let multiline = backtrace.clone().with_message(
+    "assertion `left == right` failed\n  left: 1\n right: 2".to_string(),
+  );
+  let lines = render(&multiline, OutputFormat::Errorformat);
+  assert_eq!(lines.lines().count(), 3);
+  assert!(lines.starts_with(
+    "worker.rs:42:5: assertion `left == right` failed left: 1 right: 2 in "
+  ));
+
+  let short = render(&backtrace, OutputFormat::Short);
+  assert_eq!(short.lines().count(), 1);
+  assert!(short.starts_with("boom at "));
+}
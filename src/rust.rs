@@ -0,0 +1,364 @@
+use crate::format::{BacktraceFormat, Stream};
+use crate::types::{Backtrace, Entry, Frames, Location};
+use anyhow::anyhow;
+use nom::{
+  branch::alt,
+  bytes::complete::{tag, take_until, take_while1},
+  character::complete::{
+    digit1, hex_digit1, line_ending, newline, not_line_ending, space0, space1,
+  },
+  combinator::{map, map_res, not, opt},
+  multi::{many0, separated_list},
+  sequence::{preceded, terminated, tuple},
+  IResult,
+};
+
+/// std backtraces printed by the default panic hook or `anyhow`
+#[derive(Default)]
+pub struct Rust {
+  /// Longer backtraces (stack overflows) lose their middle frames
+  pub max_frames: Option<usize>,
+}
+
+impl BacktraceFormat for Rust {
+  fn name(&self) -> &'static str {
+    "rust"
+  }
+
+  // Not sure why 2 different string
+  fn is_start(&self, line: &str) -> bool {
+    line.contains("stack backtrace:") || line.contains("Stack backtrace:")
+  }
+
+  /// A frame or its location line, both possibly prefixed by a logger, or
+  /// a caller the frame was inlined into
+  fn is_continuation(&self, line: &str) -> bool {
+    tuple((
+      parse_prefix,
+      space1,
+      alt((tag("at "), terminated(digit1, tag(":")))),
+    ))(line)
+    .is_ok()
+      || parse_inlined(line).is_ok()
+  }
+
+  /// This looks like the end of backtrace that I'm interested in
+  fn is_end(&self, line: &str) -> bool {
+    line.contains("0x0 - ")
+      || line.ends_with(" - _start")
+      || line.ends_with(" - _main") // osx ?
+      || line.ends_with(": _start")
+  }
+
+  fn parse(&self, i: &str) -> anyhow::Result<Vec<Backtrace>> {
+    // Skip the `stack backtrace:` marker
+    let i = i.split_once('\n').map_or("", |(_, frames)| frames);
+    let (_, backtrace) =
+      parse_backtrace(i).map_err(|err| anyhow!("{:?}", err))?;
+    Ok(vec![backtrace])
+  }
+
+  fn stream(&self) -> Option<Box<dyn Stream>> {
+    Some(Box::new(RustStream {
+      frames: Frames::new(self.max_frames),
+      pending: None,
+      top: None,
+    }))
+  }
+}
+
+/// Parses a frame at a time so a runaway recursion doesn't pile up in memory
+struct RustStream {
+  frames: Frames,
+  /// The last frame, its location is on the next line
+  pending: Option<Entry>,
+  /// The last numbered frame, the callers under it share its index
+  top: Option<Entry>,
+}
+
+impl Stream for RustStream {
+  fn push_line(&mut self, line: &str) {
+    if let Ok((_, location)) = parse_at(line) {
+      if let Some(mut entry) = self.pending.take() {
+        entry.set_location(location);
+        self.frames.push(entry);
+      }
+    } else if let Ok((_, top)) = parse_top(line) {
+      let entry = top_entry(top, None);
+      self.top = Some(entry.clone());
+      if let Some(previous) = self.pending.replace(entry) {
+        self.frames.push(previous);
+      }
+    } else if let (Ok((_, function)), Some(top)) =
+      (parse_inlined(line), &self.top)
+    {
+      let entry = top.caller(function.to_string());
+      if let Some(previous) = self.pending.replace(entry) {
+        self.frames.push(previous);
+      }
+    }
+  }
+
+  fn finish(mut self: Box<Self>) -> Vec<Backtrace> {
+    if let Some(entry) = self.pending.take() {
+      self.frames.push(entry);
+    }
+    vec![Backtrace::with_entries(self.frames.into_entries())]
+  }
+}
+
+/// A numbered frame and the callers it was inlined into
+fn parse_entry(i: &str) -> IResult<&str, Vec<Entry>> {
+  let (i, (top, location)) = tuple((parse_top, maybe_parse_location))(i)?;
+  let (i, callers) = many0(preceded(
+    line_ending,
+    tuple((parse_inlined, maybe_parse_location)),
+  ))(i)?;
+
+  let mut entries = vec![top_entry(top, location)];
+  for (function, location) in callers {
+    let mut caller = entries[0].caller(function.to_string());
+    if let Some(location) = location {
+      caller.set_location(location);
+    }
+    entries.push(caller);
+  }
+  Ok((i, entries))
+}
+
+fn top_entry(
+  (index, address, function): Top,
+  location: Option<Location>,
+) -> Entry {
+  let entry = Entry::new(function.to_string(), location).with_index(index);
+  match address.and_then(|a| u64::from_str_radix(a, 16).ok()) {
+    Some(address) => entry.with_address(address),
+    None => entry,
+  }
+}
+
+fn parse_backtrace(i: &str) -> IResult<&str, Backtrace> {
+  map(
+    separated_list(line_ending, parse_entry),
+    |entries: Vec<Vec<Entry>>| Backtrace::with_entries(entries.concat()),
+  )(i)
+}
+
+fn parse_prefix(i: &str) -> IResult<&str, Option<&str>> {
+  opt(take_while1(|c: char| !c.is_whitespace()))(i)
+}
+
+fn parse_symbol(i: &str) -> IResult<&str, &str> {
+  not_line_ending(i)
+}
+
+/// The index, the address if there's one, and the symbol of a frame
+type Top<'a> = (usize, Option<&'a str>, &'a str);
+
+fn parse_top(i: &str) -> IResult<&str, Top<'_>> {
+  let (i, (_, _, (index, address))) = tuple((
+    parse_prefix,
+    space1,
+    alt((terminated(parse_index, tag(" - ")), parse_short_index)),
+  ))(i)?;
+  let (i, path) = parse_symbol(i)?;
+  Ok((i, (index, address, path)))
+}
+
+/// `RUST_BACKTRACE=1` frames come without the address
+fn parse_short_index(i: &str) -> IResult<&str, (usize, Option<&str>)> {
+  let (i, index) =
+    terminated(parse_frame_number, tuple((tag(":"), space1)))(i)?;
+  Ok((i, (index, None)))
+}
+
+fn parse_index(i: &str) -> IResult<&str, (usize, Option<&str>)> {
+  let (i, (index, _, _, _)) = tuple((
+    parse_frame_number,
+    tag(":"),
+    take_while1(|c| c == ' '),
+    tag("0x"),
+  ))(i)?;
+  let (i, r) = hex_digit1(i)?;
+  Ok((i, (index, Some(r))))
+}
+
+/// `      app::run`, a caller the numbered frame above was inlined into. std
+/// indents it past the index, loggers' prefixes aren't supported
+fn parse_inlined(i: &str) -> IResult<&str, &str> {
+  let (i, _) = tuple((tag("      "), space0))(i)?;
+  let (i, _) = not(alt((tag("at "), digit1)))(i)?;
+  take_while1(|c| c != '\n' && c != '\r')(i)
+}
+
+fn parse_frame_number(i: &str) -> IResult<&str, usize> {
+  map_res(digit1, str::parse::<usize>)(i)
+}
+
+fn parse_int(i: &str) -> IResult<&str, u32> {
+  map_res(digit1, str::parse::<u32>)(i)
+}
+
+fn maybe_parse_location(i: &str) -> IResult<&str, Option<Location>> {
+  let r = parse_location(i)
+    .map(|(i, location)| (i, Some(location)))
+    .unwrap_or((i, None));
+  Ok(r)
+}
+
+fn parse_location(i: &str) -> IResult<&str, Location> {
+  preceded(newline, parse_at)(i)
+}
+
+/// `at /file.rs:1:2` line of the frame above, possibly prefixed too
+fn parse_at(i: &str) -> IResult<&str, Location> {
+  let (i, _) = tuple((parse_prefix, space1, tag("at ")))(i)?;
+  let (i, (path, _, line, _, column)) =
+    tuple((take_until(":"), tag(":"), parse_int, tag(":"), parse_int))(i)?;
+
+  let location = Location {
+    path: path.to_string(),
+    line,
+    column,
+  };
+  Ok((i, location))
+}
+
+#[test]
+fn nom_location_parser_test() {
+  let input = "\nprefix  at /file.rs:1:2";
+  let (_i, parsed) = maybe_parse_location(input).unwrap();
+  assert_eq!(
+    parsed,
+    Some(Location {
+      path: "/file.rs".to_string(),
+      line: 1,
+      column: 2,
+    })
+  );
+
+  let input = "\n  at /file.rs:1:2";
+  let (_i, parsed) = maybe_parse_location(input).unwrap();
+  assert_eq!(
+    parsed,
+    Some(Location {
+      path: "/file.rs".to_string(),
+      line: 1,
+      column: 2,
+    })
+  );
+
+  let input = "jibberish";
+  let (_i, parsed) = maybe_parse_location(input).unwrap();
+  assert!(parsed.is_none());
+}
+
+#[test]
+fn nom_top_indexed_parser_test() {
+  let input = "0:     0x55a98f23062c - std::foo::bar::123";
+  parse_index(input).unwrap();
+}
+
+#[test]
+fn nom_top_parser_test() {
+  let input = "  0:     0x55a98f23062c - std::foo::bar::123\n";
+  let _parsed = parse_top(input).unwrap();
+
+  let input = "prefix1234 0:     0x55a98f23062c - std::foo::bar::123\n";
+  let _parsed = parse_top(input).unwrap();
+}
+
+#[test]
+fn nom_short_top_parser_test() {
+  let input = "   0: std::panicking::begin_panic_handler\n";
+  let (_i, top) = parse_top(input).unwrap();
+  assert_eq!(top, (0, None, "std::panicking::begin_panic_handler"));
+
+  assert!(
+    Rust::default().is_continuation("prefix  12: core::panicking::panic_fmt")
+  );
+  assert!(Rust::default().is_continuation(
+    "             at /rustc/library/core/src/panicking.rs:72:14"
+  ));
+  assert!(!Rust::default().is_continuation("note: Some details are omitted"));
+  assert!(!Rust::default().is_continuation("---- tests::bar stdout ----"));
+}
+
+#[test]
+fn nom_entry_parser_test() {
+  let input = "  0:     0x55a98f23062c - std::foo::bar::123\n  at /rustc/348e/library/std/src/../../backtrace/src/backtrace/libunwind.rs:93:5\n";
+  let _parsed = parse_top(input).unwrap();
+}
+
+#[test]
+fn nom_full_parser_test() {
+  let input = include_str!("./tests/fixtures/panic.txt");
+  let (_i, mut backtrace) = parse_backtrace(input).unwrap();
+  assert_eq!(backtrace.entries().len(), 10);
+  // Older std prints the inlined frames at indices of their own
+  backtrace.group_inlined();
+  let inlined: Vec<bool> = backtrace
+    .entries()
+    .iter()
+    .map(|entry| entry.is_inlined())
+    .collect();
+  assert_eq!(
+    inlined,
+    [false, true, true, true, false, true, false, false, false, false]
+  );
+
+  let input = include_str!("./tests/fixtures/panic_prefixed.txt");
+  let (_i, backtrace) = parse_backtrace(input).unwrap();
+  assert_eq!(backtrace.entries().len(), 10);
+}
+
+#[test]
+fn stream_parser_test() {
+  let input = include_str!("./tests/fixtures/panic_prefixed.txt");
+  let (_i, parsed) = parse_backtrace(input).unwrap();
+
+  let mut stream = Rust::default().stream().unwrap();
+  input.lines().for_each(|line| stream.push_line(line));
+  let streamed = stream.finish();
+  assert_eq!(streamed[0].entries(), parsed.entries());
+
+  let rust = Rust {
+    max_frames: Some(4),
+  };
+  let mut stream = rust.stream().unwrap();
+  input.lines().for_each(|line| stream.push_line(line));
+  let streamed = stream.finish();
+  assert_eq!(streamed[0].entries().len(), 5);
+  assert_eq!(streamed[0].entries()[2], Entry::elided(6, "frames"));
+}
+
+#[test]
+fn inlined_parser_test() {
+  let input = include_str!("./tests/fixtures/panic_inlined.txt");
+  let parsed = Rust::default().parse(input).unwrap().remove(0);
+
+  let mut stream = Rust::default().stream().unwrap();
+  input.lines().for_each(|line| stream.push_line(line));
+  let streamed = stream.finish().remove(0);
+  assert_eq!(streamed.entries(), parsed.entries());
+
+  let mut backtrace = parsed;
+  backtrace.group_inlined();
+  backtrace.filter();
+  let entries: Vec<(&str, bool, u32)> = backtrace
+    .entries()
+    .iter()
+    .map(|entry| {
+      let line = entry.location().map_or(0, |location| location.line);
+      (entry.function(), entry.is_inlined(), line)
+    })
+    .collect();
+  assert_eq!(
+    entries,
+    [
+      ("app::parse", false, 4),
+      ("app::run", true, 9),
+      ("app::main", true, 13)
+    ]
+  );
+}
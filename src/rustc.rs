@@ -0,0 +1,284 @@
+//! Parser for rustc and cargo diagnostics, both the human readable output and
+//! the `--message-format=json` lines
+//!
+//! error[E0308]: mismatched types
+//!  --> src/main.rs:3:18
+use crate::types::{Diagnostic, Location, Severity};
+use nom::{
+  branch::alt,
+  bytes::complete::{tag, take_until},
+  character::complete::{char, digit1, space0},
+  combinator::{map, map_res, opt, rest},
+  sequence::{delimited, tuple},
+  IResult,
+};
+use serde_derive::Deserialize;
+
+/// Either an `error:` / `warning:` header or a JSON compiler message
+pub fn is_start(line: &str) -> bool {
+  matches!(
+    parse_header(line),
+    Ok((_, (Severity::Error | Severity::Warning, _, _)))
+  ) || parse_json(line).is_some()
+}
+
+/// Compiler output collected at once, a program that logs `error:` without
+/// ever ending it doesn't grow it forever
+pub const LIMIT: usize = 1 << 20;
+
+/// Lines of a diagnostic after its header: indented lines (the source and
+/// cargo's status), `-->`, `|` and `=` lines, source lines, `note:` and
+/// `help:` children, the next header and JSON messages. Anything else ends
+/// the compiler output
+pub fn is_continuation(line: &str) -> bool {
+  let numbered = line.split_once(" |").is_some_and(|(number, _)| {
+    !number.is_empty() && number.chars().all(|c| c.is_ascii_digit())
+  });
+
+  line.trim().is_empty()
+    || line.starts_with(char::is_whitespace)
+    || line.starts_with("-->")
+    || line.starts_with('|')
+    || line.starts_with('=')
+    || line.starts_with("...")
+    || line.starts_with('{')
+    || numbered
+    || parse_header(line).is_ok()
+}
+
+/// Cargo closes the build with a summary line, plain rustc with the abort
+pub fn is_end(line: &str) -> bool {
+  let trimmed = line.trim_start();
+  trimmed.starts_with("Finished ")
+    || trimmed.starts_with("Running `")
+    || line.starts_with("error: could not compile")
+    || line.starts_with("error: aborting due to")
+    || line.contains("\"reason\":\"build-finished\"")
+}
+
+/// Diagnostics without any location (the summaries) are dropped
+pub fn parse_diagnostics(i: &str) -> Vec<Diagnostic> {
+  let mut diagnostics: Vec<Diagnostic> = Vec::new();
+
+  for line in i.lines() {
+    if let Some(diagnostic) = parse_json(line) {
+      diagnostics.push(diagnostic);
+      continue;
+    }
+
+    if let Ok((_, (severity, code, message))) = parse_header(line) {
+      let diagnostic = Diagnostic {
+        severity,
+        code,
+        message: message.to_string(),
+        location: None,
+        children: Vec::new(),
+      };
+
+      match (&diagnostic.severity, diagnostics.last_mut()) {
+        (Severity::Note | Severity::Help, Some(parent)) => {
+          parent.children.push(diagnostic)
+        }
+        _ => diagnostics.push(diagnostic),
+      }
+      continue;
+    }
+
+    let Some(last) = diagnostics.last_mut() else {
+      continue;
+    };
+
+    if let Ok((_, location)) = parse_location(line) {
+      // The first span of a diagnostic is the primary one
+      let span_owner = match last.children.len() {
+        0 => last,
+        len => &mut last.children[len - 1],
+      };
+      if span_owner.location.is_none() {
+        span_owner.location = Some(location);
+      }
+    } else if let Ok((_, (severity, message))) = parse_note(line) {
+      last.children.push(Diagnostic {
+        severity,
+        code: None,
+        message: message.to_string(),
+        location: None,
+        children: Vec::new(),
+      });
+    }
+  }
+
+  diagnostics.retain(|diagnostic| diagnostic.location.is_some());
+  diagnostics
+}
+
+fn parse_severity(i: &str) -> IResult<&str, Severity> {
+  alt((
+    map(tag("error"), |_| Severity::Error),
+    map(tag("warning"), |_| Severity::Warning),
+    map(tag("note"), |_| Severity::Note),
+    map(tag("help"), |_| Severity::Help),
+  ))(i)
+}
+
+/// error[E0308]: mismatched types
+fn parse_header(i: &str) -> IResult<&str, (Severity, Option<String>, &str)> {
+  let (i, (severity, code, _, message)) = tuple((
+    parse_severity,
+    opt(delimited(char('['), take_until("]"), char(']'))),
+    tag(": "),
+    rest,
+  ))(i)?;
+  Ok((i, (severity, code.map(str::to_string), message)))
+}
+
+///   = note: `#[warn(unused_variables)]` on by default
+fn parse_note(i: &str) -> IResult<&str, (Severity, &str)> {
+  let (i, (_, _, severity, _, message)) =
+    tuple((space0, tag("= "), parse_severity, tag(": "), rest))(i)?;
+  Ok((i, (severity, message)))
+}
+
+///  --> src/main.rs:3:18
+fn parse_location(i: &str) -> IResult<&str, Location> {
+  let (i, (_, _, path, _, line, _, column)) = tuple((
+    space0,
+    alt((tag("--> "), tag("::: "))),
+    take_until(":"),
+    char(':'),
+    map_res(digit1, str::parse::<u32>),
+    char(':'),
+    map_res(digit1, str::parse::<u32>),
+  ))(i)?;
+
+  let location = Location {
+    path: path.to_string(),
+    line,
+    column,
+  };
+  Ok((i, location))
+}
+
+/// cargo wraps rustc messages, rustc `--error-format=json` prints them as is
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonLine {
+  Cargo { message: JsonDiagnostic },
+  Rustc(JsonDiagnostic),
+}
+
+#[derive(Deserialize)]
+struct JsonDiagnostic {
+  message: String,
+  code: Option<JsonCode>,
+  level: String,
+  spans: Vec<JsonSpan>,
+  children: Vec<JsonDiagnostic>,
+}
+
+#[derive(Deserialize)]
+struct JsonCode {
+  code: String,
+}
+
+#[derive(Deserialize)]
+struct JsonSpan {
+  file_name: String,
+  line_start: u32,
+  column_start: u32,
+  is_primary: bool,
+}
+
+fn parse_json(line: &str) -> Option<Diagnostic> {
+  if !line.starts_with('{') {
+    return None;
+  }
+
+  let diagnostic = match serde_json::from_str(line).ok()? {
+    JsonLine::Cargo { message } | JsonLine::Rustc(message) => message,
+  };
+  Some(diagnostic.into())
+}
+
+impl From<JsonDiagnostic> for Diagnostic {
+  fn from(json: JsonDiagnostic) -> Self {
+    let severity = match parse_severity(&json.level) {
+      Ok((_, severity)) => severity,
+      // `failure-note` and friends
+      Err(_) => Severity::Note,
+    };
+
+    let location =
+      json
+        .spans
+        .iter()
+        .find(|span| span.is_primary)
+        .map(|span| Location {
+          path: span.file_name.clone(),
+          line: span.line_start,
+          column: span.column_start,
+        });
+
+    Self {
+      severity,
+      code: json.code.map(|code| code.code),
+      message: json.message,
+      location,
+      children: json.children.into_iter().map(Diagnostic::from).collect(),
+    }
+  }
+}
+
+#[test]
+fn rustc_header_parser_test() {
+  let input = "error[E0308]: mismatched types";
+  let (_i, (severity, code, message)) = parse_header(input).unwrap();
+  assert_eq!(severity, Severity::Error);
+  assert_eq!(code.as_deref(), Some("E0308"));
+  assert_eq!(message, "mismatched types");
+
+  assert!(parse_header("  = note: indented").is_err());
+  assert!(parse_note("  = note: indented").is_ok());
+}
+
+#[test]
+fn rustc_full_parser_test() {
+  let input = include_str!("./tests/fixtures/rustc.txt");
+  let diagnostics = parse_diagnostics(input);
+  assert_eq!(diagnostics.len(), 3);
+
+  assert_eq!(diagnostics[0].severity, Severity::Warning);
+  assert_eq!(diagnostics[0].children.len(), 1);
+
+  let location = diagnostics[2].children[0].location.as_ref().unwrap();
+  assert_eq!(location.to_string(), "src/lib.rs:1:8");
+  assert_eq!(diagnostics[2].children[1].severity, Severity::Help);
+}
+
+#[test]
+fn rustc_continuation_test() {
+  let input = include_str!("./tests/fixtures/rustc.txt");
+  let first_error = input.lines().position(|line| line.starts_with("error["));
+  let diagnostic = input.lines().skip(first_error.unwrap()).take(21);
+  assert!(diagnostic.clone().all(is_continuation));
+
+  assert!(!is_continuation(
+    "Some errors have detailed explanations: E0061."
+  ));
+  assert!(!is_continuation("[INFO] server listening on :8080"));
+}
+
+#[test]
+fn rustc_json_parser_test() {
+  let input = include_str!("./tests/fixtures/rustc.json");
+  assert!(input.lines().next().is_some_and(|line| !is_start(line)));
+  assert!(input.lines().last().is_some_and(is_end));
+
+  let diagnostics = parse_diagnostics(input);
+  assert_eq!(diagnostics.len(), 2);
+
+  assert_eq!(diagnostics[0].code.as_deref(), Some("E0308"));
+  let location = diagnostics[0].location.as_ref().unwrap();
+  assert_eq!(location.to_string(), "src/main.rs:3:18");
+  assert_eq!(diagnostics[0].children[0].severity, Severity::Help);
+}
@@ -0,0 +1,180 @@
+//! SARIF 2.1 log of the backtraces for code scanning in CI
+//!
+//! Every backtrace is a result, the top frame is its location and the frames
+//! are both a `stack` and a `codeFlow`.
+use crate::types::{Backtrace, Location};
+use serde_json::{json, Value};
+use std::{fs, path::Path};
+
+#[derive(Default)]
+pub struct Sarif {
+  results: Vec<Value>,
+}
+
+impl Sarif {
+  /// Paths under `root` are written relative to it, that's what code scanning
+  /// matches with the files of the repository
+  pub fn push(&mut self, backtrace: &Backtrace, root: &Path) {
+    let frames: Vec<(&str, &Location)> = backtrace
+      .entries()
+      .iter()
+      .filter_map(|entry| Some((entry.function(), entry.location()?)))
+      .collect();
+
+    // The filter keeps dependency and std frames with user code inlined
+    let user = frames.iter().find(|(_, location)| under(location, root));
+    let Some((top, location)) = user.or(frames.first()) else {
+      return;
+    };
+
+    let message = match (backtrace.test(), backtrace.message()) {
+      (Some(test), Some(message)) => format!("{}: {}", test, message),
+      (Some(test), None) => format!("{} panicked in {}", test, top),
+      (None, Some(message)) => message.to_string(),
+      (None, None) => format!("panicked in {}", top),
+    };
+
+    let stack: Vec<Value> = frames
+      .iter()
+      .map(|(function, location)| {
+        json!({
+          "location": {
+            "physicalLocation": physical_location(location, root),
+            "message": { "text": function },
+          },
+        })
+      })
+      .collect();
+    let thread_flow: Vec<Value> = stack
+      .iter()
+      .map(|frame| json!({ "location": frame["location"] }))
+      .collect();
+    let related: Vec<Value> = backtrace
+      .labels()
+      .iter()
+      .enumerate()
+      .map(|(id, label)| {
+        json!({
+          "id": id,
+          "physicalLocation": physical_location(&label.location, root),
+          "message": { "text": label.label },
+        })
+      })
+      .collect();
+
+    self.results.push(json!({
+      "ruleId": "panic",
+      "level": "error",
+      "message": { "text": message },
+      "locations": [{ "physicalLocation": physical_location(location, root) }],
+      "relatedLocations": related,
+      "stacks": [{ "message": { "text": "Backtrace" }, "frames": stack }],
+      "codeFlows": [{ "threadFlows": [{ "locations": thread_flow }] }],
+    }));
+  }
+
+  fn log(&self) -> Value {
+    json!({
+      "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+      "version": "2.1.0",
+      "runs": [{
+        "tool": {
+          "driver": {
+            "name": "stacky",
+            "version": env!("CARGO_PKG_VERSION"),
+            "informationUri": "https://github.com/fudini/stacky-rs",
+            "rules": [{
+              "id": "panic",
+              "shortDescription": { "text": "The program panicked" },
+            }],
+          },
+        },
+        "results": self.results,
+      }],
+    })
+  }
+
+  /// Written even without results, CI uploads whatever is at the path
+  pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+    fs::write(path, serde_json::to_string_pretty(&self.log())?)?;
+    Ok(())
+  }
+}
+
+/// Relative paths are relative to the root already
+fn under(location: &Location, root: &Path) -> bool {
+  let path = Path::new(&location.path);
+  path.is_relative() || path.starts_with(root)
+}
+
+fn physical_location(location: &Location, root: &Path) -> Value {
+  let path = Path::new(&location.path);
+  let relative = match path.is_relative() {
+    true => Ok(path),
+    false => path.strip_prefix(root),
+  };
+  let artifact = match relative {
+    Ok(relative) => json!({
+      "uri": relative.to_string_lossy(),
+      "uriBaseId": "%SRCROOT%",
+    }),
+    Err(_) => json!({ "uri": format!("file://{}", location.path) }),
+  };
+
+  let mut region = json!({ "startLine": location.line.max(1) });
+  if location.column > 0 {
+    region["startColumn"] = json!(location.column);
+  }
+
+  json!({ "artifactLocation": artifact, "region": region })
+}
+
+#[test]
+fn sarif_test() {
+  use crate::types::Entry;
+  use crate::{format::BacktraceFormat, lldb::Lldb};
+
+  let input = include_str!("./tests/fixtures/lldb.txt");
+  let mut sarif = Sarif::default();
+  for backtrace in Lldb.parse(input).unwrap() {
+    sarif.push(&backtrace, Path::new("/home/user/myapp"));
+  }
+
+  let log = sarif.log();
+  let results = log["runs"][0]["results"].as_array().unwrap();
+  assert_eq!(results.len(), 2);
+  assert_eq!(
+    results[0]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+    "worker.rs"
+  );
+  assert_eq!(
+    results[0]["stacks"][0]["frames"].as_array().unwrap().len(),
+    3
+  );
+  assert_eq!(
+    results[1]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+    "src/worker.rs"
+  );
+
+  let location = |path: &str| Location {
+    path: path.to_string(),
+    line: 1,
+    column: 0,
+  };
+  let registry = "/home/user/.cargo/registry/src/serde-1.0.0/src/de.rs";
+  let backtrace = Backtrace::with_entries(vec![
+    Entry::new("serde::de::parse".to_string(), Some(location(registry))),
+    Entry::new(
+      "myapp::load".to_string(),
+      Some(location("/home/user/myapp/src/load.rs")),
+    ),
+  ]);
+  let mut sarif = Sarif::default();
+  sarif.push(&backtrace, Path::new("/home/user/myapp"));
+  let log = sarif.log();
+  assert_eq!(
+    log["runs"][0]["results"][0]["locations"][0]["physicalLocation"]
+      ["artifactLocation"]["uri"],
+    "src/load.rs"
+  );
+}
@@ -0,0 +1,283 @@
+//! Where the reports are delivered, neovim or a command run for each of them
+//!
+//! Commands are templates filled in with the first located frame:
+//! `code -g {path}:{line}:{col}`
+use crate::types::{Location, Report};
+use serde_derive::{Deserialize, Serialize};
+use std::{env, fs, io::Write, process};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sink {
+  /// Every neovim instance whose cwd the report has a location in
+  Nvim,
+  /// A compilation-mode buffer in the matching emacs servers
+  Emacs,
+  /// The `stacky lsp` servers of any editor
+  Lsp,
+  /// `edit` in a client of the Kakoune session, sent with `kak -p`
+  Kak,
+  Command(Template),
+}
+
+impl Sink {
+  /// `nvim`, an editor with a built-in template or a template of its own
+  pub fn parse(sink: &str) -> Self {
+    Self::builtin(sink).unwrap_or_else(|| {
+      Self::Command(Template {
+        command: sink.to_string(),
+        terminal: false,
+      })
+    })
+  }
+
+  /// The sinks stacky knows by name, `None` for a template
+  pub fn builtin(sink: &str) -> Option<Self> {
+    let terminal = matches!(sink, "editor" | "hx" | "helix");
+    let template = match sink {
+      "nvim" | "neovim" => return Some(Self::Nvim),
+      "emacs" => return Some(Self::Emacs),
+      "lsp" => return Some(Self::Lsp),
+      "editor" => "$EDITOR +{line} {path}",
+      "code" | "vscode" => "code -g {path}:{line}:{col}",
+      "hx" | "helix" => "hx {path}:{line}:{col}",
+      "kak" | "kakoune" => return Some(Self::Kak),
+      "emacsclient" => "emacsclient -n +{line}:{col} {path}",
+      _ => return None,
+    };
+
+    Some(Self::Command(Template {
+      command: template.to_string(),
+      terminal,
+    }))
+  }
+}
+
+/// What the placeholders are filled in with
+struct Target<'a> {
+  location: &'a Location,
+  function: &'a str,
+  message: &'a str,
+}
+
+impl<'a> Target<'a> {
+  /// The first frame with a location, it's a user frame after the filter
+  fn of(report: &'a Report) -> Option<Self> {
+    match report {
+      Report::Backtrace(backtrace) => {
+        let message = backtrace.message().unwrap_or("");
+        backtrace.entries().iter().find_map(|entry| {
+          Some(Target {
+            location: entry.location()?,
+            function: entry.function(),
+            message,
+          })
+        })
+      }
+      Report::Diagnostics(diagnostics) => {
+        diagnostics.iter().find_map(|diagnostic| {
+          Some(Target {
+            location: diagnostic.location.as_ref()?,
+            function: "",
+            message: &diagnostic.message,
+          })
+        })
+      }
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Template {
+  /// Split on whitespace after `$VARIABLES` are expanded, a placeholder
+  /// stays a single argument whatever it's filled in with
+  command: String,
+  /// Takes over the terminal, it only runs once the input has ended
+  terminal: bool,
+}
+
+impl Template {
+  /// `{path}`, `{line}`, `{col}` or `{column}`, `{function}` and `{message}`
+  fn fill(text: &str, target: &Target) -> String {
+    let Target {
+      location,
+      function,
+      message,
+    } = target;
+    let message = message.lines().next().unwrap_or("");
+
+    text
+      .replace("{path}", &location.path)
+      .replace("{line}", &location.line.to_string())
+      .replace("{col}", &location.column.max(1).to_string())
+      .replace("{column}", &location.column.max(1).to_string())
+      .replace("{function}", function)
+      .replace("{message}", message)
+  }
+
+  fn args(&self, target: &Target) -> Vec<String> {
+    expand_env(&self.command)
+      .split_whitespace()
+      .map(|word| Self::fill(word, target))
+      .collect()
+  }
+
+  pub fn is_terminal(&self) -> bool {
+    self.terminal
+  }
+
+  /// Runs the command for the report and waits for it
+  pub fn run(&self, report: &Report, verbose: bool) {
+    if is_repeat(report) {
+      return;
+    }
+    let Some(target) = Target::of(report) else {
+      return;
+    };
+
+    let args = self.args(&target);
+    let Some((program, args)) = args.split_first() else {
+      return;
+    };
+    if verbose {
+      eprintln!("Stacky: RUNNING {} {:?}", program, args);
+    }
+
+    let mut command = process::Command::new(program);
+    command.args(args);
+    // stdin is the pipe stacky reads, terminal editors need the terminal
+    match fs::File::open("/dev/tty") {
+      Ok(tty) if self.terminal => command.stdin(tty),
+      _ => command.stdin(process::Stdio::null()),
+    };
+
+    if let Err(e) = command.status() {
+      eprintln!("Stacky error running `{}`: {}", program, e);
+    }
+  }
+}
+
+/// The counters of repeated backtraces would open the file every second
+pub fn is_repeat(report: &Report) -> bool {
+  matches!(report, Report::Backtrace(backtrace) if backtrace.count() > 1)
+}
+
+/// Opens the location in a client of the Kakoune session
+pub fn kak(report: &Report, session: &str, client: &str, verbose: bool) {
+  if is_repeat(report) {
+    return;
+  }
+  let Some(target) = Target::of(report) else {
+    return;
+  };
+
+  let command = kak_command(target.location, client);
+  if verbose {
+    eprintln!("Stacky: KAK {} {}", session, command);
+  }
+
+  let result = process::Command::new("kak")
+    .args(["-p", session])
+    .stdin(process::Stdio::piped())
+    .spawn()
+    .and_then(|mut child| {
+      if let Some(mut stdin) = child.stdin.take() {
+        writeln!(stdin, "{}", command)?;
+      }
+      child.wait()
+    });
+  if let Err(e) = result {
+    eprintln!("Stacky error running `kak -p {}`: {}", session, e);
+  }
+}
+
+/// `edit` only works in a client, so it's quoted once more for
+/// `evaluate-commands`
+fn kak_command(location: &Location, client: &str) -> String {
+  let edit = format!(
+    "edit -existing {} {} {}",
+    kak_quote(&location.path),
+    location.line,
+    location.column.max(1)
+  );
+  format!(
+    "evaluate-commands -client {} {}",
+    kak_quote(client),
+    kak_quote(&edit)
+  )
+}
+
+/// Kakoune's `'...'` string, nothing in it is expanded and `'` is doubled
+fn kak_quote(text: &str) -> String {
+  format!("'{}'", text.replace('\'', "''"))
+}
+
+/// `$NAME` words and prefixes, unset variables become empty
+fn expand_env(command: &str) -> String {
+  let mut expanded = String::with_capacity(command.len());
+  let mut rest = command;
+
+  while let Some(start) = rest.find('$') {
+    expanded.push_str(&rest[..start]);
+    let name_len = rest[start + 1..]
+      .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+      .unwrap_or(rest.len() - start - 1);
+    let name = &rest[start + 1..start + 1 + name_len];
+
+    match name.is_empty() {
+      true => expanded.push('$'),
+      false => expanded.push_str(&env::var(name).unwrap_or_default()),
+    }
+    rest = &rest[start + 1 + name_len..];
+  }
+
+  expanded.push_str(rest);
+  expanded
+}
+
+#[test]
+fn sink_test() {
+  use crate::types::{Backtrace, Entry};
+
+  let location = Location {
+    path: "/home/me/my app/src/main.rs".to_string(),
+    line: 12,
+    column: 0,
+  };
+  let backtrace = Backtrace::with_entries(vec![
+    Entry::new("std::panicking::begin_panic".to_string(), None),
+    Entry::new("app::main".to_string(), Some(location)),
+  ])
+  .with_message("boom".to_string());
+  let report = Report::Backtrace(backtrace);
+  let target = Target::of(&report).unwrap();
+
+  let Sink::Command(code) = Sink::parse("code") else {
+    panic!("code has a template");
+  };
+  assert_eq!(
+    code.args(&target),
+    ["code", "-g", "/home/me/my app/src/main.rs:12:1"]
+  );
+
+  let Sink::Command(custom) = Sink::parse("notify-send {function} {message}")
+  else {
+    panic!("anything else is a template");
+  };
+  assert_eq!(custom.args(&target), ["notify-send", "app::main", "boom"]);
+
+  assert_eq!(Sink::parse("nvim"), Sink::Nvim);
+  assert_eq!(Sink::parse("kak"), Sink::Kak);
+  assert!(matches!(Sink::parse("helix"), Sink::Command(t) if t.is_terminal()));
+  let location = Location {
+    path: "/home/me/it's {x}.rs".to_string(),
+    line: 3,
+    column: 0,
+  };
+  assert_eq!(
+    kak_command(&location, "client0"),
+    "evaluate-commands -client 'client0' \
+     'edit -existing ''/home/me/it''''s {x}.rs'' 3 1'"
+  );
+  assert_eq!(expand_env("$STACKY_UNSET_VARIABLE +1 $"), " +1 $");
+}
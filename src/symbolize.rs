@@ -0,0 +1,239 @@
+//! Resolves raw frame addresses with the DWARF of the binaries they belong to
+//!
+//!   55a98f214000-55a98f25c000 r-xp 00014000 08:01 1234   /path/to/prog
+use crate::config::Config;
+use crate::types::Location;
+use addr2line::Loader;
+use object::{Object, ObjectSegment};
+use std::{
+  collections::HashMap,
+  fs,
+  path::{Path, PathBuf},
+};
+
+/// What the debug info knows about an address
+pub struct Symbol {
+  pub function: Option<String>,
+  pub location: Option<Location>,
+}
+
+/// Executable mapping of a binary in the address space of the process
+#[derive(Debug, PartialEq)]
+struct Mapping {
+  start: u64,
+  end: u64,
+  offset: u64,
+  path: PathBuf,
+}
+
+struct Binary {
+  loader: Loader,
+  /// File offset, file size and virtual address of each segment
+  segments: Vec<(u64, u64, u64)>,
+}
+
+impl Binary {
+  fn load(path: &Path) -> Option<Self> {
+    let data = fs::read(path).ok()?;
+    let object = object::File::parse(&*data).ok()?;
+
+    let segments = object
+      .segments()
+      .map(|segment| {
+        let (offset, size) = segment.file_range();
+        (offset, size, segment.address())
+      })
+      .collect();
+
+    // Stripped binaries point to a separate file with the debug info
+    let debug = match object.section_by_name(".debug_info") {
+      Some(_) => None,
+      None => debug_file(path, &object),
+    };
+    let loader = Loader::new(debug.as_deref().unwrap_or(path)).ok()?;
+
+    Some(Self { loader, segments })
+  }
+
+  /// Virtual address of the given offset into the file
+  fn address(&self, offset: u64) -> Option<u64> {
+    self
+      .segments
+      .iter()
+      .find(|(start, size, _)| (*start..start + size).contains(&offset))
+      .map(|(start, _, address)| offset - start + address)
+  }
+
+  fn lookup(&self, address: u64) -> Option<Symbol> {
+    let mut frames = self.loader.find_frames(address).ok()?;
+
+    // The innermost inlined frame is the one the address is in
+    if let Ok(Some(frame)) = frames.next() {
+      let function = frame
+        .function
+        .as_ref()
+        .and_then(|name| name.raw_name().ok())
+        .map(|name| name.to_string());
+      let location = frame.location.and_then(|location| {
+        Some(Location {
+          path: location.file?.to_string(),
+          line: location.line?,
+          column: location.column.unwrap_or(0),
+        })
+      });
+      return Some(Symbol { function, location });
+    }
+
+    // No DWARF, the symbol table is better than nothing
+    let function = self.loader.find_symbol(address)?;
+    Some(Symbol {
+      function: Some(function.to_string()),
+      location: None,
+    })
+  }
+}
+
+/// `/usr/lib/debug/.build-id/ab/cdef.debug` or the file named by the
+/// `.gnu_debuglink` section
+fn debug_file(path: &Path, object: &object::File) -> Option<PathBuf> {
+  let debug = Path::new("/usr/lib/debug");
+
+  if let Ok(Some(id)) = object.build_id() {
+    let hex: String = id.iter().map(|byte| format!("{:02x}", byte)).collect();
+    if hex.len() > 2 {
+      let file = debug
+        .join(".build-id")
+        .join(&hex[..2])
+        .join(format!("{}.debug", &hex[2..]));
+      if file.is_file() {
+        return Some(file);
+      }
+    }
+  }
+
+  let (name, _crc) = object.gnu_debuglink().ok()??;
+  let name = std::str::from_utf8(name).ok()?;
+  let dir = path.parent()?;
+  let relative = dir.strip_prefix("/").unwrap_or(dir);
+
+  [
+    dir.join(name),
+    dir.join(".debug").join(name),
+    debug.join(relative).join(name),
+  ]
+  .into_iter()
+  .find(|file| file.is_file())
+}
+
+fn parse_mapping(line: &str) -> Option<Mapping> {
+  let mut fields = line.split_whitespace();
+  let (start, end) = fields.next()?.split_once('-')?;
+  let perms = fields.next()?;
+  let offset = fields.next()?;
+  let path = fields.nth(2)?;
+
+  if !perms.contains('x') || !path.starts_with('/') {
+    return None;
+  }
+
+  Some(Mapping {
+    start: u64::from_str_radix(start, 16).ok()?,
+    end: u64::from_str_radix(end, 16).ok()?,
+    offset: u64::from_str_radix(offset, 16).ok()?,
+    // Paths with spaces are split too
+    path: line[line.find(path)?..].into(),
+  })
+}
+
+pub struct Symbolizer {
+  maps: Vec<Mapping>,
+  executable: Option<PathBuf>,
+  /// Binaries are loaded once, `None` when they have no debug info
+  binaries: HashMap<PathBuf, Option<Binary>>,
+}
+
+impl Symbolizer {
+  /// The maps are read right away, `/proc/<pid>/maps` is gone with the
+  /// process
+  pub fn new(config: &Config) -> Self {
+    let maps = config
+      .maps
+      .as_ref()
+      .and_then(|path| fs::read_to_string(path).ok())
+      .map(|maps| maps.lines().filter_map(parse_mapping).collect())
+      .unwrap_or_default();
+
+    Self {
+      maps,
+      executable: config.executable.clone(),
+      binaries: HashMap::new(),
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    !self.maps.is_empty() || self.executable.is_some()
+  }
+
+  /// Without the maps the addresses are taken as they are, that's only right
+  /// for binaries that aren't position independent
+  pub fn lookup(&mut self, address: u64) -> Option<Symbol> {
+    let (path, offset) = match self
+      .maps
+      .iter()
+      .find(|mapping| (mapping.start..mapping.end).contains(&address))
+    {
+      Some(mapping) => {
+        let offset = address - mapping.start + mapping.offset;
+        (mapping.path.clone(), Some(offset))
+      }
+      None => (self.executable.clone()?, None),
+    };
+
+    let binary = self
+      .binaries
+      .entry(path)
+      .or_insert_with_key(|path| Binary::load(path))
+      .as_ref()?;
+
+    let address = match offset {
+      Some(offset) => binary.address(offset)?,
+      None => address,
+    };
+
+    // Return addresses point to the instruction after the call
+    binary.lookup(address.saturating_sub(1))
+  }
+}
+
+#[test]
+fn maps_parser_test() {
+  let line =
+    "55a98f214000-55a98f25c000 r-xp 00014000 08:01 1234   /home/me/my prog";
+  assert_eq!(
+    parse_mapping(line),
+    Some(Mapping {
+      start: 0x55a98f214000,
+      end: 0x55a98f25c000,
+      offset: 0x14000,
+      path: "/home/me/my prog".into(),
+    })
+  );
+
+  let line = "7ffd5b3c1000-7ffd5b3e2000 rw-p 00000000 00:00 0   [stack]";
+  assert_eq!(parse_mapping(line), None);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn symbolizer_test() {
+  let config = Config {
+    maps: Some("/proc/self/maps".into()),
+    ..Config::default()
+  };
+  let mut symbolizer = Symbolizer::new(&config);
+
+  let address = maps_parser_test as fn() as usize as u64;
+  let symbol = symbolizer.lookup(address + 1).unwrap();
+  assert!(symbol.function.unwrap().contains("maps_parser_test"));
+  assert!(symbol.location.unwrap().path.ends_with("symbolize.rs"));
+}
@@ -0,0 +1,394 @@
+//! `--tui`, a full screen browser of the backtraces for when there's no
+//! neovim to send them to
+//!
+//! Keys come from the terminal even when stdin is the pipe stacky read.
+use crate::types::{Backtrace, Entry, Location, Source};
+use anyhow::bail;
+use ratatui::{
+  crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+  layout::{Constraint, Layout, Rect},
+  style::{Color, Modifier, Style},
+  text::{Line, Span},
+  widgets::{Block, List, ListItem, ListState, Paragraph},
+  DefaultTerminal, Frame,
+};
+use std::{env, fs, path::Path, process};
+
+enum Action {
+  None,
+  Quit,
+  Open(Location),
+}
+
+struct App {
+  /// Unfiltered, `Backtrace::kept` tells which frames the filter keeps
+  backtraces: Vec<Backtrace>,
+  current: usize,
+  /// The frames the filter drops are shown too
+  full: bool,
+  query: String,
+  /// Keys go to the query
+  typing: bool,
+  selected: usize,
+}
+
+impl App {
+  fn new(backtraces: Vec<Backtrace>) -> Self {
+    Self {
+      current: backtraces.len().saturating_sub(1),
+      backtraces,
+      full: false,
+      query: String::new(),
+      typing: false,
+      selected: 0,
+    }
+  }
+
+  fn backtrace(&self) -> &Backtrace {
+    &self.backtraces[self.current]
+  }
+
+  /// Indices of the frames in the list
+  fn visible(&self) -> Vec<usize> {
+    let backtrace = self.backtrace();
+    let kept = backtrace.kept();
+    let query = self.query.to_lowercase();
+
+    let entries = backtrace.entries().iter().enumerate();
+    entries
+      .filter(|(index, _)| self.full || kept[*index])
+      .filter(|(_, entry)| query.is_empty() || matches(entry, &query))
+      .map(|(index, _)| index)
+      .collect()
+  }
+
+  fn entry(&self) -> Option<&Entry> {
+    let index = *self.visible().get(self.selected)?;
+    self.backtrace().entries().get(index)
+  }
+
+  fn key(&mut self, code: KeyCode) -> Action {
+    if self.typing {
+      match code {
+        KeyCode::Esc => {
+          self.query.clear();
+          self.typing = false;
+        }
+        KeyCode::Enter => self.typing = false,
+        KeyCode::Backspace => {
+          self.query.pop();
+        }
+        KeyCode::Char(c) => self.query.push(c),
+        _ => {}
+      }
+      self.selected = 0;
+      return Action::None;
+    }
+
+    match code {
+      KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+      KeyCode::Down | KeyCode::Char('j') => self.selected += 1,
+      KeyCode::Up | KeyCode::Char('k') => {
+        self.selected = self.selected.saturating_sub(1)
+      }
+      KeyCode::Home | KeyCode::Char('g') => self.selected = 0,
+      KeyCode::End | KeyCode::Char('G') => self.selected = usize::MAX,
+      KeyCode::Char('/') => self.typing = true,
+      KeyCode::Char('f') => {
+        self.full = !self.full;
+        self.selected = 0;
+      }
+      KeyCode::Tab | KeyCode::Right | KeyCode::Char('l') => {
+        self.current = (self.current + 1) % self.backtraces.len();
+        self.selected = 0;
+      }
+      KeyCode::BackTab | KeyCode::Left | KeyCode::Char('h') => {
+        let len = self.backtraces.len();
+        self.current = (self.current + len - 1) % len;
+        self.selected = 0;
+      }
+      KeyCode::Enter => {
+        if let Some(location) = self.entry().and_then(Entry::location) {
+          return Action::Open(location.clone());
+        }
+      }
+      _ => {}
+    }
+
+    let visible = self.visible().len();
+    self.selected = self.selected.min(visible.saturating_sub(1));
+    Action::None
+  }
+
+  fn draw(&self, frame: &mut Frame) {
+    let header = self.header();
+    let [top, body, bottom] = Layout::vertical([
+      Constraint::Length(header.len() as u16),
+      Constraint::Min(0),
+      Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [frames, preview] =
+      Layout::horizontal([Constraint::Percentage(50); 2]).areas(body);
+
+    frame.render_widget(Paragraph::new(header), top);
+    self.draw_frames(frame, frames);
+    self.draw_preview(frame, preview);
+
+    let footer = match self.typing {
+      true => Line::from(format!("/{}", self.query)),
+      false => Line::styled(
+        "↑↓ move  / filter  f full  ⏎ open  ←→ backtrace  q quit",
+        Style::new().fg(Color::DarkGray),
+      ),
+    };
+    frame.render_widget(footer, bottom);
+  }
+
+  fn header(&self) -> Vec<Line<'_>> {
+    let backtrace = self.backtrace();
+    let mut title = vec![Span::styled(
+      format!("[{}/{}]", self.current + 1, self.backtraces.len()),
+      Style::new().add_modifier(Modifier::BOLD),
+    )];
+    if let Some(test) = backtrace.test() {
+      title.push(Span::styled(format!(" {}", test), Color::Magenta));
+    }
+    if let Some(thread) = backtrace.thread() {
+      title.push(Span::raw(format!(" thread '{}'", thread)));
+    }
+
+    let mut lines = vec![Line::from(title)];
+    let message = backtrace.message().into_iter().flat_map(str::lines);
+    lines.extend(message.take(3).map(|line| Line::styled(line, Color::Red)));
+    if let Some(assertion) = backtrace.assertion() {
+      lines.push(Line::raw(assertion.to_string()));
+    }
+    lines
+  }
+
+  fn draw_frames(&self, frame: &mut Frame, area: Rect) {
+    let backtrace = self.backtrace();
+    let kept = backtrace.kept();
+    let items: Vec<ListItem> = self
+      .visible()
+      .into_iter()
+      .map(|index| {
+        let entry = &backtrace.entries()[index];
+        if entry.elided_count() > 0 {
+          let marker =
+            format!("… {} {} …", entry.elided_count(), entry.function());
+          return ListItem::new(Line::styled(marker, Color::DarkGray));
+        }
+
+        let function = match kept[index] {
+          true => Style::new().fg(Color::Blue),
+          false => Style::new().fg(Color::DarkGray),
+        };
+        let mut spans = vec![Span::raw(match entry.is_inlined() {
+          true => "  ",
+          false => "",
+        })];
+        spans.push(Span::styled(entry.function(), function));
+        if let Some(location) = entry.location() {
+          spans.push(Span::styled(format!(" {}", location), Color::Green));
+        }
+        ListItem::new(Line::from(spans))
+      })
+      .collect();
+
+    let title = match self.full {
+      true => " frames (all) ",
+      false => " frames ",
+    };
+    let list = List::new(items)
+      .block(Block::bordered().title(title))
+      .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(Some(self.selected));
+    frame.render_stateful_widget(list, area, &mut state);
+  }
+
+  fn draw_preview(&self, frame: &mut Frame, area: Rect) {
+    let location = self.entry().and_then(Entry::location);
+    let context = area.height.saturating_sub(4) as usize / 2;
+    let source = location.and_then(|l| Source::read(l, context));
+
+    let (Some(location), Some(source)) = (location, source) else {
+      let block = Block::bordered().title(" source ");
+      frame.render_widget(Paragraph::new("no source").block(block), area);
+      return;
+    };
+
+    let width = (source.first as usize + source.lines.len())
+      .to_string()
+      .len();
+    let mut lines = Vec::new();
+    for (number, line) in (source.first..).zip(&source.lines) {
+      // Tabs don't render in a buffer cell
+      let code = line.replace('\t', "    ");
+      if number != location.line {
+        let gutter = format!("  {:>width$} │ ", number);
+        lines.push(Line::from(vec![
+          Span::styled(gutter, Color::DarkGray),
+          Span::raw(code),
+        ]));
+        continue;
+      }
+
+      let gutter = format!("> {:>width$} │ ", number);
+      lines.push(Line::from(vec![
+        Span::styled(gutter, Color::Red),
+        Span::styled(code, Style::new().add_modifier(Modifier::BOLD)),
+      ]));
+      if location.column > 0 {
+        let before = line.chars().take(location.column as usize - 1);
+        let indent: usize = before.map(|c| if c == '\t' { 4 } else { 1 }).sum();
+        let caret = format!("  {:>width$} │ {}^", "", " ".repeat(indent));
+        lines.push(Line::styled(caret, Color::Red));
+      }
+    }
+
+    let title = format!(" {} ", location.path);
+    frame.render_widget(
+      Paragraph::new(lines).block(Block::bordered().title(title)),
+      area,
+    );
+  }
+
+  fn run(&mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<()> {
+    loop {
+      terminal.draw(|frame| self.draw(frame))?;
+
+      let Event::Key(key) = event::read()? else {
+        continue;
+      };
+      if key.kind != KeyEventKind::Press {
+        continue;
+      }
+      if key.modifiers.contains(KeyModifiers::CONTROL)
+        && key.code == KeyCode::Char('c')
+      {
+        return Ok(());
+      }
+
+      match self.key(key.code) {
+        Action::None => {}
+        Action::Quit => return Ok(()),
+        Action::Open(location) => {
+          ratatui::restore();
+          let opened = open(&location);
+          *terminal = ratatui::try_init()?;
+          opened?;
+        }
+      }
+    }
+  }
+}
+
+/// Case insensitive, the query is lowercase already
+fn matches(entry: &Entry, query: &str) -> bool {
+  let location = entry.location().map(|l| l.path.as_str()).unwrap_or("");
+  entry.function().to_lowercase().contains(query)
+    || location.to_lowercase().contains(query)
+}
+
+/// `$VISUAL` or `$EDITOR` at the line and column of the location
+fn open(location: &Location) -> anyhow::Result<()> {
+  let editor = env::var("VISUAL")
+    .or_else(|_| env::var("EDITOR"))
+    .unwrap_or_else(|_| "vi".to_string());
+  let mut words = editor.split_whitespace();
+  let Some(program) = words.next() else {
+    bail!("`$EDITOR` is empty");
+  };
+
+  let mut command = process::Command::new(program);
+  command.args(words).args(editor_args(program, location));
+  // stdin may be the pipe stacky read
+  if let Ok(tty) = fs::File::open("/dev/tty") {
+    command.stdin(tty);
+  }
+  command.status()?;
+  Ok(())
+}
+
+/// Every editor has its own way to be told the column
+fn editor_args(program: &str, location: &Location) -> Vec<String> {
+  let Location { path, line, column } = location;
+  let column = (*column).max(1);
+  let name = Path::new(program)
+    .file_name()
+    .and_then(|name| name.to_str())
+    .unwrap_or(program);
+
+  match name {
+    "vi" | "vim" | "nvim" | "gvim" => {
+      vec![format!("+call cursor({line}, {column})"), path.clone()]
+    }
+    "code" | "codium" | "cursor" => {
+      vec!["-g".to_string(), format!("{path}:{line}:{column}")]
+    }
+    "hx" | "helix" | "zed" | "subl" => vec![format!("{path}:{line}:{column}")],
+    "emacs" | "emacsclient" | "kak" => {
+      vec![format!("+{line}:{column}"), path.clone()]
+    }
+    "nano" => vec![format!("+{line},{column}"), path.clone()],
+    _ => vec![format!("+{line}"), path.clone()],
+  }
+}
+
+/// Browses the backtraces, the last one first
+pub fn run(backtraces: Vec<Backtrace>) -> anyhow::Result<()> {
+  if backtraces.is_empty() {
+    bail!("no backtrace to browse");
+  }
+
+  let mut app = App::new(backtraces);
+  let mut terminal = ratatui::try_init()?;
+  let result = app.run(&mut terminal);
+  ratatui::restore();
+  result
+}
+
+#[test]
+fn tui_test() {
+  let location = |path: &str, line| Location {
+    path: path.to_string(),
+    line,
+    column: 5,
+  };
+  let backtrace = Backtrace::with_entries(vec![
+    Entry::new(
+      "core::panicking::panic".to_string(),
+      Some(location("/rustc/library/core/src/panicking.rs", 72)),
+    ),
+    Entry::new("app::run".to_string(), Some(location("src/run.rs", 42))),
+    Entry::new("app::main".to_string(), Some(location("src/main.rs", 8))),
+  ]);
+  let mut app = App::new(vec![backtrace]);
+
+  assert_eq!(app.visible().len(), 2);
+  app.key(KeyCode::Char('f'));
+  assert_eq!(app.visible().len(), 3);
+
+  app.key(KeyCode::Char('/'));
+  for c in "RUN".chars() {
+    app.key(KeyCode::Char(c));
+  }
+  app.key(KeyCode::Enter);
+  assert_eq!(app.visible().len(), 1);
+  assert_eq!(app.entry().unwrap().function(), "app::run");
+
+  app.key(KeyCode::Down);
+  assert_eq!(app.selected, 0);
+  match app.key(KeyCode::Enter) {
+    Action::Open(location) => assert_eq!(location.line, 42),
+    _ => panic!("the frame has a location"),
+  }
+
+  let location = app.entry().unwrap().location().unwrap();
+  assert_eq!(
+    editor_args("/usr/bin/nvim", location),
+    ["+call cursor(42, 5)", "src/run.rs"]
+  );
+}
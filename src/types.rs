@@ -1,103 +1,1006 @@
-use crate::utils::color;
-use serde_derive::Serialize;
-use std::fmt;
+use crate::symbolize::Symbolizer;
+use crate::utils::{color, demangle, hyperlink};
+use serde::{Deserializer, Serializer};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+  collections::{HashMap, VecDeque},
+  fmt, fs,
+  path::Path,
+};
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Entry {
   function: String,
+  /// Demangled name with its hash, only sent with `keep_hash`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  symbol: Option<String>,
   location: Option<Location>,
+  /// Position in the original backtrace, elided and filtered frames included
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  index: Option<usize>,
+  /// Instruction pointer, a string since it doesn't fit in a lua number
+  #[serde(
+    default,
+    serialize_with = "serialize_address",
+    deserialize_with = "deserialize_address",
+    skip_serializing_if = "Option::is_none"
+  )]
+  address: Option<u64>,
+  /// Caller the frame above was inlined into, printed under the same index
+  #[serde(default, skip_serializing_if = "is_false")]
+  inlined: bool,
+  /// Number of frames this marker stands for, `function` describes them
+  #[serde(default, skip_serializing_if = "is_zero")]
+  elided: usize,
+  /// Shown in the terminal only, the editor has the file
+  #[serde(skip)]
+  source: Option<Source>,
+}
+
+/// Lines around the location, read from the local file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+  pub first: u32,
+  pub lines: Vec<String>,
+}
+
+impl Source {
+  pub fn read(location: &Location, context: usize) -> Option<Self> {
+    let file = fs::read_to_string(&location.path).ok()?;
+    Self::around(&file, location, context)
+  }
+
+  /// The lines around the location in the contents of its file
+  fn around(file: &str, location: &Location, context: usize) -> Option<Self> {
+    let line = location.line as usize;
+    if line == 0 {
+      return None;
+    }
+
+    let first = line.saturating_sub(context).max(1);
+    let lines: Vec<String> = file
+      .lines()
+      .skip(first - 1)
+      .take(line + context + 1 - first)
+      .map(str::to_string)
+      .collect();
+
+    // The file changed since the program was built
+    if lines.len() < line + 1 - first {
+      return None;
+    }
+
+    Some(Self {
+      first: first as u32,
+      lines,
+    })
+  }
+
+  /// rustc style snippet with the failing line highlighted and a caret under
+  /// the column
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+    location: &Location,
+  ) -> fmt::Result {
+    let last = self.first as usize + self.lines.len() - 1;
+    let width = last.to_string().len();
+
+    for (number, code) in (self.first..).zip(&self.lines) {
+      writeln!(f)?;
+      if number != location.line {
+        color(f, 8, 0, &format!("  {:>width$} | ", number));
+        write!(f, "{}", code)?;
+        continue;
+      }
+
+      color(f, 1, 0, &format!("> {:>width$} | ", number));
+      color(f, 7, 0, code);
+      if location.column > 0 {
+        // Tabs stay tabs so the caret lines up
+        let indent: String = code
+          .chars()
+          .take(location.column as usize - 1)
+          .map(|c| if c == '\t' { '\t' } else { ' ' })
+          .collect();
+        writeln!(f)?;
+        color(f, 8, 0, &format!("  {:>width$} | ", ""));
+        color(f, 1, 0, &format!("{}^", indent));
+      }
+    }
+    Ok(())
+  }
+}
+
+fn is_zero(count: &usize) -> bool {
+  *count == 0
+}
+
+fn is_false(value: &bool) -> bool {
+  !value
+}
+
+fn is_once(count: &usize) -> bool {
+  *count <= 1
+}
+
+fn serialize_address<S: Serializer>(
+  address: &Option<u64>,
+  serializer: S,
+) -> Result<S::Ok, S::Error> {
+  match address {
+    Some(address) => serializer.serialize_str(&format!("{:#x}", address)),
+    None => serializer.serialize_none(),
+  }
+}
+
+fn deserialize_address<'de, D: Deserializer<'de>>(
+  deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+  let Some(address) =
+    <Option<String> as serde::Deserialize>::deserialize(deserializer)?
+  else {
+    return Ok(None);
+  };
+  let hex = address.trim_start_matches("0x");
+  u64::from_str_radix(hex, 16)
+    .map(Some)
+    .map_err(serde::de::Error::custom)
 }
 
 impl Entry {
+  /// Mangled names are demangled right away
   pub fn new(function: String, location: Option<Location>) -> Self {
-    Self { function, location }
+    let (function, symbol) = demangle(&function);
+    Self {
+      function,
+      symbol,
+      location,
+      index: None,
+      address: None,
+      inlined: false,
+      elided: 0,
+      source: None,
+    }
+  }
+
+  pub fn with_index(mut self, index: usize) -> Self {
+    self.index = Some(index);
+    self
+  }
+
+  pub fn with_address(mut self, address: u64) -> Self {
+    self.address = Some(address);
+    self
+  }
+
+  /// Caller the frame was inlined into, it shares the index and address
+  pub fn caller(&self, function: String) -> Self {
+    Self {
+      index: self.index,
+      address: self.address,
+      ..Self::new(function, None)
+    }
+  }
+
+  /// Marker in place of frames that were left out
+  pub fn elided(count: usize, what: &str) -> Self {
+    Self {
+      function: what.to_string(),
+      symbol: None,
+      location: None,
+      index: None,
+      address: None,
+      inlined: false,
+      elided: count,
+      source: None,
+    }
+  }
+
+  pub fn function(&self) -> &str {
+    &self.function
+  }
+
+  pub fn location(&self) -> Option<&Location> {
+    self.location.as_ref()
+  }
+
+  /// Loaded by `Backtrace::load_sources`
+  pub fn source(&self) -> Option<&Source> {
+    self.source.as_ref()
+  }
+
+  pub fn is_inlined(&self) -> bool {
+    self.inlined
+  }
+
+  /// Marker of elided frames
+  pub fn elided_count(&self) -> usize {
+    self.elided
+  }
+
+  /// Same function, the line in it may have moved
+  pub fn same_frame(&self, other: &Entry) -> bool {
+    self.function == other.function && self.elided == other.elided
+  }
+
+  /// Same frame at a different line or file
+  pub fn moved(&self, other: &Entry) -> bool {
+    match (&self.location, &other.location) {
+      (Some(a), Some(b)) => (&a.path, a.line) != (&b.path, b.line),
+      _ => false,
+    }
+  }
+
+  pub fn set_function(&mut self, function: &str) {
+    (self.function, self.symbol) = demangle(function);
+  }
+
+  pub fn set_location(&mut self, location: Location) {
+    self.location = Some(location);
   }
 }
 
 impl fmt::Display for Entry {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.elided > 0 {
+      color(f, 8, 0, &format!("… {} {} …", self.elided, self.function));
+      return Ok(());
+    }
+
+    if self.inlined {
+      write!(f, "  ")?;
+    }
     color(f, 4, 0, &self.function);
     if let Some(location) = &self.location {
       write!(f, " ")?;
-      color(f, 2, 0, &location.path);
-      write!(f, ":")?;
-      color(f, 3, 0, &location.line.to_string());
-      write!(f, ":")?;
-      color(f, 3, 0, &location.column.to_string());
+      hyperlink(f, location.parts(), |f| {
+        color(f, 2, 0, &location.path);
+        write!(f, ":")?;
+        color(f, 3, 0, &location.line.to_string());
+        write!(f, ":")?;
+        color(f, 3, 0, &location.column.to_string());
+        Ok(())
+      })?;
+
+      if let Some(source) = &self.source {
+        source.fmt(f, location)?;
+      }
     }
     Ok(())
   }
 }
 
-#[derive(Debug, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Location {
   pub path: String,
   pub line: u32,
   pub column: u32,
 }
 
+impl Location {
+  /// Relative paths are resolved against `base` so they can be matched with
+  /// the CWD of neovim instances
+  pub fn resolve(&mut self, base: &Path) {
+    if Path::new(&self.path).is_relative() {
+      let path = self.path.trim_start_matches("./");
+      self.path = base.join(path).to_string_lossy().into_owned();
+    }
+  }
+
+  fn parts(&self) -> (&str, u32, u32) {
+    (&self.path, self.line, self.column)
+  }
+}
+
 impl fmt::Display for Location {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     write!(f, "{}:{}:{}", self.path, self.line, self.column)
   }
 }
 
-#[derive(Debug, Serialize)]
-pub struct Backtrace(Vec<Entry>);
+/// Keeps the first and the last frames of a backtrace, the ones in the
+/// middle are only counted
+pub struct Frames {
+  max: Option<usize>,
+  head: Vec<Entry>,
+  tail: VecDeque<Entry>,
+  elided: usize,
+}
+
+impl Frames {
+  pub fn new(max: Option<usize>) -> Self {
+    Self {
+      max,
+      head: Vec::new(),
+      tail: VecDeque::new(),
+      elided: 0,
+    }
+  }
+
+  pub fn push(&mut self, entry: Entry) {
+    let Some(max) = self.max else {
+      self.head.push(entry);
+      return;
+    };
+
+    if self.head.len() < max - max / 2 {
+      self.head.push(entry);
+      return;
+    }
+
+    self.tail.push_back(entry);
+    if self.tail.len() > max / 2 {
+      self.tail.pop_front();
+      self.elided += 1;
+    }
+  }
+
+  pub fn into_entries(self) -> Vec<Entry> {
+    let mut entries = self.head;
+    if self.elided > 0 {
+      entries.push(Entry::elided(self.elided, "frames"));
+    }
+    entries.extend(self.tail);
+    entries
+  }
+}
+
+/// Location that isn't a frame but helps to explain the failure
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Label {
+  pub label: String,
+  pub location: Location,
+}
+
+impl fmt::Display for Label {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    color(f, 5, 0, &self.label);
+    write!(f, " ")?;
+    hyperlink(f, self.location.parts(), |f| {
+      color(f, 2, 0, &self.location.to_string());
+      Ok(())
+    })
+  }
+}
+
+/// Values of a failed `assert_eq!` or `assert_ne!`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Assertion {
+  pub left: String,
+  pub right: String,
+}
+
+impl fmt::Display for Assertion {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "left={} right={}", self.left, self.right)
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Backtrace {
+  /// Name of the test that panicked
+  #[serde(skip_serializing_if = "Option::is_none")]
+  test: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  thread: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  message: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  assertion: Option<Assertion>,
+  entries: Vec<Entry>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  labels: Vec<Label>,
+  /// tracing span trace, outermost span last
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  spans: Vec<Entry>,
+  /// Same for every repeat of the panic, see `Backtrace::fingerprint`
+  #[serde(skip_serializing_if = "Option::is_none")]
+  fingerprint: Option<String>,
+  /// Times the panic happened in a row, the editor updates its counter
+  #[serde(skip_serializing_if = "is_once")]
+  count: usize,
+}
 
 impl Backtrace {
   pub fn with_entries(entries: Vec<Entry>) -> Self {
-    Self(entries)
+    Self {
+      entries,
+      ..Self::default()
+    }
+  }
+
+  pub fn with_message(mut self, message: String) -> Self {
+    self.message = Some(message);
+    self
+  }
+
+  pub fn with_labels(mut self, labels: Vec<Label>) -> Self {
+    self.labels = labels;
+    self
+  }
+
+  pub fn with_spans(mut self, spans: Vec<Entry>) -> Self {
+    self.spans = spans;
+    self
+  }
+
+  pub fn with_test(mut self, test: String) -> Self {
+    self.test = Some(test);
+    self
+  }
+
+  pub fn with_thread(mut self, thread: String) -> Self {
+    self.thread = Some(thread);
+    self
+  }
+
+  pub fn with_assertion(mut self, assertion: Assertion) -> Self {
+    self.assertion = Some(assertion);
+    self
   }
 
-  #[cfg(test)]
   pub fn entries(&self) -> &Vec<Entry> {
-    &self.0
+    &self.entries
   }
 
-  /// Loops through all entries and check if any of them matches the given location
+  pub fn labels(&self) -> &Vec<Label> {
+    &self.labels
+  }
+
+  #[cfg(test)]
+  pub fn spans(&self) -> &Vec<Entry> {
+    &self.spans
+  }
+
+  pub fn test(&self) -> Option<&str> {
+    self.test.as_deref()
+  }
+
+  pub fn thread(&self) -> Option<&str> {
+    self.thread.as_deref()
+  }
+
+  pub fn message(&self) -> Option<&str> {
+    self.message.as_deref()
+  }
+
+  pub fn assertion(&self) -> Option<&Assertion> {
+    self.assertion.as_ref()
+  }
+
+  pub fn count(&self) -> usize {
+    self.count.max(1)
+  }
+
+  pub fn set_count(&mut self, count: usize) {
+    self.count = count;
+  }
+
+  /// Hash of the names of the frames `filter` keeps, line numbers and
+  /// symbol hashes left out so a rebuild or an edit nearby doesn't change it.
+  /// All the frames count when none of them are kept
+  pub fn fingerprint(&mut self) -> &str {
+    if self.fingerprint.is_none() {
+      let kept = self.kept();
+      let mut names: Vec<&str> = self
+        .entries
+        .iter()
+        .zip(&kept)
+        .filter(|(entry, kept)| **kept && entry.elided == 0)
+        .map(|(entry, _)| entry.function.as_str())
+        .collect();
+      if names.is_empty() {
+        names = self.entries.iter().map(|e| e.function.as_str()).collect();
+      }
+
+      // FNV-1a, stable between runs and builds unlike `DefaultHasher`
+      let mut hash: u64 = 0xcbf29ce484222325;
+      for name in names {
+        let name = strip_closure(name).unwrap_or(name);
+        for byte in name.bytes().chain([0]) {
+          hash ^= byte as u64;
+          hash = hash.wrapping_mul(0x100000001b3);
+        }
+      }
+      self.fingerprint = Some(format!("{:016x}", hash));
+    }
+    self.fingerprint.as_deref().unwrap_or_default()
+  }
+
+  /// Loops through all entries, spans and labels and check if any of them
+  /// matches the given location
   pub fn has_location(&self, location: &str) -> bool {
-    self
-      .0
+    let entries = self
+      .entries
       .iter()
-      .find(|entry| {
-        entry
-          .location
-          .as_ref()
-          .map(|l| l.path.starts_with(location))
-          .unwrap_or(false)
-      })
-      .is_some()
+      .chain(&self.spans)
+      .filter_map(|entry| entry.location.as_ref());
+    let labels = self.labels.iter().map(|label| &label.location);
+
+    entries.chain(labels).any(|l| l.path.starts_with(location))
   }
 
-  /// Clean up from unwanted entries
-  pub fn filter(&mut self) {
-    self.0.retain(|entry| {
+  /// Relative paths (Miri, debuggers) are resolved against `base`
+  pub fn resolve(&mut self, base: &Path) {
+    let entries = self
+      .entries
+      .iter_mut()
+      .chain(&mut self.spans)
+      .filter_map(|entry| entry.location.as_mut());
+    let labels = self.labels.iter_mut().map(|label| &mut label.location);
+
+    for location in entries.chain(labels) {
+      location.resolve(base);
+    }
+  }
+
+  /// Fills in the frames that only have an address, `<unknown>` ones also
+  /// get their name
+  pub fn symbolize(&mut self, symbolizer: &mut Symbolizer) {
+    for entry in &mut self.entries {
+      if entry.location.is_some() {
+        continue;
+      }
+      let Some(symbol) = entry.address.and_then(|a| symbolizer.lookup(a))
+      else {
+        continue;
+      };
+
+      let unnamed = entry.function.is_empty() || entry.function == "<unknown>";
+      if let (true, Some(function)) = (unnamed, &symbol.function) {
+        entry.set_function(function);
+      }
+      entry.location = symbol.location;
+    }
+  }
+
+  /// The hashes only tell apart symbols with the same name, most of the time
+  /// they're noise
+  pub fn strip_symbols(&mut self) {
+    for entry in self.entries.iter_mut().chain(&mut self.spans) {
+      entry.symbol = None;
+    }
+  }
+
+  /// Runs of async runtime frames become a single marker and the closures
+  /// they poll are the bodies of async fns
+  pub fn collapse_runtime(&mut self) {
+    let mut caller_is_async = false;
+    for entry in self.entries.iter_mut().rev() {
+      if runtime(&entry.function).is_some() {
+        caller_is_async = true;
+        continue;
+      }
+
+      match strip_closure(&entry.function) {
+        Some(function) if caller_is_async => {
+          entry.function = format!("async fn {}", function);
+        }
+        _ => caller_is_async = false,
+      }
+    }
+
+    let mut entries = Vec::with_capacity(self.entries.len());
+    let mut run: Vec<Entry> = Vec::new();
+    for entry in std::mem::take(&mut self.entries) {
+      if runtime(&entry.function).is_none() {
+        collapse(&mut run, &mut entries);
+        entries.push(entry);
+      } else {
+        run.push(entry);
+      }
+    }
+    collapse(&mut run, &mut entries);
+
+    self.entries = entries;
+  }
+
+  /// Frames printed under the same index, or at the same address like older
+  /// std prints them, are one physical frame. The first is the function the
+  /// address is in, the ones after it are the callers it was inlined into.
+  /// The same function at the same address again is recursion
+  pub fn group_inlined(&mut self) {
+    let mut previous: Option<&Entry> = None;
+    let mut inlined = Vec::with_capacity(self.entries.len());
+    for entry in &self.entries {
+      inlined.push(previous.is_some_and(|previous| {
+        let same_index = entry.index.is_some() && entry.index == previous.index;
+        let same_address = entry.address.is_some()
+          && entry.address == previous.address
+          && entry.function != previous.function;
+        same_index || same_address
+      }));
+      previous = Some(entry);
+    }
+    for (entry, inlined) in self.entries.iter_mut().zip(inlined) {
+      entry.inlined = inlined;
+    }
+  }
+
+  /// Reads the lines around the locations of the frames under the root, each
+  /// file once, the paths have to be resolved already
+  pub fn load_sources(&mut self, root: &Path, context: usize) {
+    let mut files: HashMap<String, Option<String>> = HashMap::new();
+    for entry in &mut self.entries {
       let Some(location) = &entry.location else {
-        return false;
+        continue;
       };
+      if !Path::new(&location.path).starts_with(root) {
+        continue;
+      }
 
-      let path = &location.path;
+      let file = files
+        .entry(location.path.clone())
+        .or_insert_with(|| fs::read_to_string(&location.path).ok());
+      entry.source = file
+        .as_deref()
+        .and_then(|file| Source::around(file, location, context));
+    }
+  }
+
+  /// Clean up from unwanted entries
+  pub fn filter(&mut self) {
+    let mut keep = self.kept().into_iter();
+    self.entries.retain(|_| keep.next().unwrap_or(false));
+  }
 
-      // TODO: configurable somehow?
-      !(entry.function.contains("__libc")
-        || entry.function.contains("start_thread")
-        || entry.function.contains("__GI___clone3")
-        || path == "_start"
-        || path.contains("/rustc/")
-        || path.contains("/sysdeps/")
-        || path == "")
-    });
+  /// Which entries `filter` keeps, physical frames stay when any of their
+  /// inlined frames do
+  pub fn kept(&self) -> Vec<bool> {
+    let mut keep: Vec<bool> = self.entries.iter().map(is_wanted).collect();
+
+    let mut physical = None;
+    for (index, entry) in self.entries.iter().enumerate() {
+      if !entry.inlined {
+        physical = Some(index);
+      } else if let (true, Some(physical)) = (keep[index], physical) {
+        keep[physical] = true;
+      }
+    }
+    keep
   }
 }
 
+/// Name of the async runtime the function belongs to
+fn runtime(function: &str) -> Option<&'static str> {
+  let function = function.trim_start_matches(['<', '&']);
+  let runtimes = [
+    ("tokio::", "tokio"),
+    ("futures::", "futures"),
+    ("futures_util::", "futures"),
+    ("futures_core::", "futures"),
+    ("futures_executor::", "futures"),
+    ("async_std::", "async-std"),
+    ("async_task::", "async-std"),
+    ("async_executor::", "smol"),
+    ("smol::", "smol"),
+    ("core::future::", "async"),
+    ("std::future::", "async"),
+    ("core::pin::Pin<P> as core::future::", "async"),
+  ];
+
+  runtimes
+    .iter()
+    .find(|(prefix, _)| function.starts_with(prefix))
+    .map(|(_, name)| *name)
+}
+
+/// `handler::{{closure}}`, `{closure#0}` for v0 symbols
+fn strip_closure(function: &str) -> Option<&str> {
+  let (function, closure) = function.rsplit_once("::")?;
+  (closure == "{{closure}}" || closure.starts_with("{closure#"))
+    .then_some(function)
+}
+
+/// Frames of the run are replaced with a marker named after the runtime most
+/// of them belong to, a single frame stays as it is
+fn collapse(run: &mut Vec<Entry>, entries: &mut Vec<Entry>) {
+  if run.len() < 2 {
+    entries.append(run);
+    return;
+  }
+
+  let mut names: Vec<&str> = run
+    .iter()
+    .filter_map(|entry| runtime(&entry.function))
+    .collect();
+  names.sort_unstable();
+  let name = names
+    .chunk_by(|a, b| a == b)
+    .max_by_key(|chunk| chunk.len())
+    .map_or("async", |chunk| chunk[0]);
+
+  entries.push(Entry::elided(run.len(), &format!("{} frames", name)));
+  run.clear();
+}
+
+fn is_wanted(entry: &Entry) -> bool {
+  if entry.elided > 0 {
+    return true;
+  }
+
+  let Some(location) = &entry.location else {
+    return false;
+  };
+
+  let path = &location.path;
+
+  // TODO: configurable somehow?
+  !(entry.function.contains("__libc")
+    || entry.function.contains("start_thread")
+    || entry.function.contains("__GI___clone3")
+    || path == "_start"
+    || path.contains("/rustc/")
+    || path.contains("/sysdeps/")
+    || path.is_empty())
+}
+
 impl fmt::Display for Backtrace {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     color(f, 1, 0, "\n--- BACKTRACE START ---\n");
-    for entry in &self.0 {
+    if let Some(test) = &self.test {
+      color(f, 5, 0, test);
+      writeln!(f)?;
+    }
+    if let Some(message) = &self.message {
+      writeln!(f, "{}", message)?;
+    }
+    if let Some(assertion) = &self.assertion {
+      writeln!(f, "{}", assertion)?;
+    }
+    for entry in &self.entries {
       writeln!(f, "{}", entry)?;
     }
+    for label in &self.labels {
+      writeln!(f, "{}", label)?;
+    }
+    if !self.spans.is_empty() {
+      color(f, 1, 0, "--- SPANTRACE ---\n");
+    }
+    for span in &self.spans {
+      writeln!(f, "{}", span)?;
+    }
     color(f, 1, 0, "--- BACKTRACE END ------\n");
     Ok(())
   }
 }
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+  Error,
+  Warning,
+  Note,
+  Help,
+}
+
+/// Compiler message, `note:` and `help:` are its children
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+  pub severity: Severity,
+  pub code: Option<String>,
+  pub message: String,
+  pub location: Option<Location>,
+  pub children: Vec<Diagnostic>,
+}
+
+impl Diagnostic {
+  /// Checks the diagnostic and its children for the given location
+  pub fn has_location(&self, location: &str) -> bool {
+    self
+      .location
+      .as_ref()
+      .map(|l| l.path.starts_with(location))
+      .unwrap_or(false)
+      || self
+        .children
+        .iter()
+        .any(|child| child.has_location(location))
+  }
+
+  pub fn resolve(&mut self, base: &Path) {
+    if let Some(location) = &mut self.location {
+      location.resolve(base);
+    }
+    for child in &mut self.children {
+      child.resolve(base);
+    }
+  }
+}
+
+/// Everything that gets delivered to neovim, other stacky processes get it as
+/// `{"backtrace": {...}}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Report {
+  Backtrace(Backtrace),
+  Diagnostics(Vec<Diagnostic>),
+}
+
+impl Report {
+  pub fn has_location(&self, location: &str) -> bool {
+    match self {
+      Self::Backtrace(backtrace) => backtrace.has_location(location),
+      Self::Diagnostics(diagnostics) => diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.has_location(location)),
+    }
+  }
+}
+
+#[test]
+fn frames_test() {
+  let mut frames = Frames::new(Some(5));
+  for index in 0..100 {
+    frames.push(Entry::new(index.to_string(), None));
+  }
+
+  let functions: Vec<String> = frames
+    .into_entries()
+    .iter()
+    .map(|entry| entry.function.clone())
+    .collect();
+  assert_eq!(functions, ["0", "1", "2", "frames", "98", "99"]);
+}
+
+#[test]
+fn group_inlined_test() {
+  let location = |path: &str| {
+    Some(Location {
+      path: path.to_string(),
+      line: 1,
+      column: 1,
+    })
+  };
+  let physical = |function: &str, path: &str, index: usize| {
+    Entry::new(function.to_string(), location(path))
+      .with_index(index)
+      .with_address(index as u64)
+  };
+  let caller = |entry: &Entry, function: &str, path: &str| {
+    let mut caller = entry.caller(function.to_string());
+    caller.set_location(location(path).unwrap());
+    caller
+  };
+  // Printed order, the function the address is in goes first
+  let begin = physical("begin_panic", "/rustc/panicking.rs", 0);
+  let call_once = caller(&begin, "call_once", "/rustc/ops.rs");
+  let parse = physical("parse", "src/main.rs", 1);
+  let run = caller(&parse, "run", "src/main.rs");
+  let main = physical("main", "src/main.rs", 2);
+  let entries = vec![begin, call_once, parse, run, main];
+
+  let mut backtrace = Backtrace::with_entries(entries);
+  backtrace.group_inlined();
+  backtrace.filter();
+
+  let functions: Vec<(&str, bool)> = backtrace
+    .entries
+    .iter()
+    .map(|entry| (entry.function.as_str(), entry.inlined))
+    .collect();
+  assert_eq!(
+    functions,
+    [("parse", false), ("run", true), ("main", false)]
+  );
+}
+
+#[test]
+fn group_recursion_test() {
+  // A stack overflow returns to the same address at every index
+  let entries = (0..4)
+    .map(|index| {
+      Entry::new("app::recurse".to_string(), None)
+        .with_index(index)
+        .with_address(0x55555555d1f2)
+    })
+    .collect();
+
+  let mut backtrace = Backtrace::with_entries(entries);
+  backtrace.group_inlined();
+  assert!(backtrace.entries.iter().all(|entry| !entry.inlined));
+}
+
+#[test]
+fn fingerprint_test() {
+  let backtrace = |frames: &[(&str, u32)]| {
+    let entries = frames
+      .iter()
+      .map(|(function, line)| {
+        let location = Location {
+          path: "/home/me/app/src/main.rs".to_string(),
+          line: *line,
+          column: 5,
+        };
+        Entry::new(function.to_string(), Some(location))
+      })
+      .collect();
+    Backtrace::with_entries(entries)
+  };
+
+  let mut built = backtrace(&[
+    ("app::parse::h0123456789abcdef", 4),
+    ("app::main::hfedcba9876543210", 12),
+  ]);
+  // Rebuilt after an edit above both frames
+  let mut rebuilt = backtrace(&[
+    ("app::parse::h1111111111111111", 6),
+    ("app::main::h2222222222222222", 14),
+  ]);
+  let mut elsewhere = backtrace(&[("app::load", 4), ("app::main", 12)]);
+
+  let fingerprint = built.fingerprint().to_string();
+  assert_eq!(rebuilt.fingerprint(), fingerprint);
+  assert_ne!(elsewhere.fingerprint(), fingerprint);
+}
+
+#[test]
+fn collapse_runtime_test() {
+  let functions = [
+    "app::handler::{{closure}}",
+    "<core::pin::Pin<P> as core::future::future::Future>::poll",
+    "app::serve::{{closure}}",
+    "tokio::runtime::task::core::Core<T,S>::poll::{{closure}}",
+    "<tokio::runtime::task::harness::Harness<T,S>>::poll",
+    "tokio::runtime::scheduler::current_thread::CoreGuard::block_on",
+    "app::main",
+  ];
+  let entries = functions
+    .iter()
+    .map(|function| Entry::new(function.to_string(), None))
+    .collect();
+
+  let mut backtrace = Backtrace::with_entries(entries);
+  backtrace.collapse_runtime();
+
+  let functions: Vec<String> = backtrace
+    .entries
+    .iter()
+    .map(|entry| entry.to_string())
+    .collect();
+  assert!(functions[0].contains("async fn app::handler"));
+  assert!(functions[1].contains("<core::pin::Pin<P> as"));
+  assert!(functions[2].contains("async fn app::serve"));
+  assert!(functions[3].contains("… 3 tokio frames …"));
+  assert!(functions[4].contains("app::main"));
+}
+
+#[test]
+fn source_test() {
+  let location = Location {
+    path: concat!(env!("CARGO_MANIFEST_DIR"), "/src/tests/fixtures/eyre.txt")
+      .to_string(),
+    line: 1,
+    column: 1,
+  };
+  let source = Source::read(&location, 2).unwrap();
+  assert_eq!(source.first, 1);
+  assert_eq!(source.lines.len(), 3);
+
+  let location = Location {
+    line: 100_000,
+    ..location
+  };
+  assert!(Source::read(&location, 2).is_none());
+
+  let location = Location {
+    line: 1,
+    ..location
+  };
+  let mut backtrace = Backtrace::with_entries(vec![
+    Entry::new("app::main".to_string(), Some(location.clone())),
+    Entry::new("app::run".to_string(), Some(location)),
+  ]);
+  backtrace.load_sources(Path::new("/nowhere"), 2);
+  assert!(backtrace
+    .entries()
+    .iter()
+    .all(|entry| entry.source.is_none()));
+  backtrace.load_sources(Path::new(env!("CARGO_MANIFEST_DIR")), 2);
+  assert!(backtrace
+    .entries()
+    .iter()
+    .all(|entry| entry.source.is_some()));
+}
@@ -1,7 +1,11 @@
 use std::{
   env, fmt,
   fs::{self, DirEntry},
-  path::PathBuf,
+  io,
+  os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt},
+  path::{Path, PathBuf},
+  process::Command,
+  sync::OnceLock,
 };
 
 fn entry_to_path(dir_entry: DirEntry) -> Option<PathBuf> {
@@ -41,7 +45,334 @@ fn get_pipes(dir: &str) -> Vec<PathBuf> {
   nvim_pipes.collect()
 }
 
+/// Sockets of the emacs servers, `$XDG_RUNTIME_DIR/emacs/server` or
+/// `/tmp/emacs1000/server` for older ones
+pub fn get_emacs_sockets() -> Vec<PathBuf> {
+  let mut dirs: Vec<PathBuf> = env::var("XDG_RUNTIME_DIR")
+    .map(|dir| PathBuf::from(dir).join("emacs"))
+    .into_iter()
+    .collect();
+
+  let tmp = env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+  if let Ok(entries) = fs::read_dir(tmp) {
+    let emacs = entries.filter_map(Result::ok).map(|entry| entry.path());
+    dirs.extend(emacs.filter(|path| {
+      let name = path.file_name().and_then(|name| name.to_str());
+      name.is_some_and(|name| name.starts_with("emacs"))
+    }));
+  }
+
+  dirs
+    .iter()
+    .filter_map(|dir| fs::read_dir(dir).ok())
+    .flatten()
+    .filter_map(Result::ok)
+    .filter(|entry| entry.file_type().is_ok_and(|t| t.is_socket()))
+    .map(|entry| entry.path())
+    .collect()
+}
+
+/// `$XDG_RUNTIME_DIR/stacky` or `/tmp/stacky-<uid>`, where stacky processes
+/// find each other. It's created private, and refused when someone else owns
+/// it or could write to it, since their sockets would get the backtraces
+pub fn runtime_dir() -> io::Result<PathBuf> {
+  // SAFETY: getuid can't fail
+  let uid = unsafe { libc::getuid() };
+  let dir = match env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+    Some(dir) => PathBuf::from(dir).join("stacky"),
+    None => env::temp_dir().join(format!("stacky-{}", uid)),
+  };
+
+  private_dir(&dir, uid)?;
+  Ok(dir)
+}
+
+/// Creates the directory with mode 0700, an existing one has to be a
+/// directory of the user that only they can write to
+fn private_dir(dir: &Path, uid: u32) -> io::Result<()> {
+  match fs::DirBuilder::new().mode(0o700).create(dir) {
+    Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+    _ => {}
+  }
+
+  let metadata = fs::symlink_metadata(dir)?;
+  if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o022 != 0
+  {
+    return Err(io::Error::new(
+      io::ErrorKind::PermissionDenied,
+      format!("{} isn't a private directory of this user", dir.display()),
+    ));
+  }
+  Ok(())
+}
+
+/// Trimmed stdout of a successful command
+pub fn run(program: &str, args: &[&str]) -> Option<String> {
+  let output = Command::new(program).args(args).output().ok()?;
+  output
+    .status
+    .success()
+    .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// `2024-03-01 13:05:09` UTC of a unix timestamp
+pub fn format_time(timestamp: u64) -> String {
+  let (days, seconds) = (timestamp / 86400, timestamp % 86400);
+
+  // Howard Hinnant's civil_from_days
+  let z = days as i64 + 719468;
+  let era = z.div_euclid(146097);
+  let doe = z.rem_euclid(146097);
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = yoe + era * 400 + i64::from(month <= 2);
+
+  format!(
+    "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+    year,
+    month,
+    day,
+    seconds / 3600,
+    seconds / 60 % 60,
+    seconds % 60
+  )
+}
+
+/// How the terminal output looks, decided once at startup
+pub struct Style {
+  pub color: bool,
+  /// URI template of the OSC 8 hyperlinks, `None` without links
+  pub links: Option<String>,
+}
+
+static STYLE: OnceLock<Style> = OnceLock::new();
+
+pub fn set_style(style: Style) {
+  let _ = STYLE.set(style);
+}
+
+fn style() -> &'static Style {
+  STYLE.get_or_init(|| Style {
+    color: true,
+    links: None,
+  })
+}
+
 /// Colors
 pub fn color(f: &mut fmt::Formatter, fg: u8, bg: u8, string: &str) {
-  let _ = write!(f, "\x1b[38;5;{fg}m\x1b[48;5;{bg}m{string}\x1b[0m");
+  let _ = if style().color {
+    write!(f, "\x1b[38;5;{fg}m\x1b[48;5;{bg}m{string}\x1b[0m")
+  } else {
+    write!(f, "{string}")
+  };
+}
+
+/// Wraps what `text` writes in an OSC 8 hyperlink to the location, the
+/// template has `{path}`, `{line}` and `{column}` placeholders
+pub fn hyperlink(
+  f: &mut fmt::Formatter,
+  (path, line, column): (&str, u32, u32),
+  text: impl FnOnce(&mut fmt::Formatter) -> fmt::Result,
+) -> fmt::Result {
+  let Some(template) = &style().links else {
+    return text(f);
+  };
+
+  let uri = template
+    .replace("{path}", &encode_path(path))
+    .replace("{line}", &line.to_string())
+    .replace("{column}", &column.to_string());
+  write!(f, "\x1b]8;;{uri}\x1b\\")?;
+  text(f)?;
+  write!(f, "\x1b]8;;\x1b\\")
+}
+
+/// Percent-encodes everything but the unreserved characters and `/`
+pub fn encode_path(path: &str) -> String {
+  let mut encoded = String::with_capacity(path.len());
+  for byte in path.bytes() {
+    match byte {
+      b'a'..=b'z'
+      | b'A'..=b'Z'
+      | b'0'..=b'9'
+      | b'-'
+      | b'.'
+      | b'_'
+      | b'~'
+      | b'/' => encoded.push(byte as char),
+      _ => encoded.push_str(&format!("%{:02X}", byte)),
+    }
+  }
+  encoded
+}
+
+/// Strips the trailing `::h<hash>` of a legacy mangled Rust symbol
+fn strip_hash(function: &str) -> &str {
+  match function.rsplit_once("::h") {
+    Some((name, hash))
+      if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) =>
+    {
+      name
+    }
+    _ => function,
+  }
+}
+
+/// Drops the `[e28293b1aa0f68bd]` crate disambiguators of a v0 symbol that
+/// was demangled already
+fn strip_disambiguators(function: &str) -> String {
+  let mut stripped = String::with_capacity(function.len());
+  let mut rest = function;
+
+  while let Some(start) = rest.find('[') {
+    let (before, after) = rest.split_at(start);
+    stripped.push_str(before);
+
+    let is_ident = before
+      .chars()
+      .next_back()
+      .is_some_and(|c| c.is_alphanumeric() || c == '_');
+    match after[1..].split_once(']') {
+      Some((hash, after))
+        if is_ident
+          && !hash.is_empty()
+          && hash.chars().all(|c| c.is_ascii_hexdigit()) =>
+      {
+        rest = after;
+      }
+      _ => {
+        stripped.push('[');
+        rest = &after[1..];
+      }
+    }
+  }
+
+  stripped.push_str(rest);
+  stripped
+}
+
+/// Readable name of a legacy or v0 Rust, Itanium C++ or already demangled
+/// symbol, along with the full name when its hashes were left out
+pub fn demangle(symbol: &str) -> (String, Option<String>) {
+  // Span text, `??` of gdb and other labels stay as they are
+  if !is_symbol(symbol) {
+    return (symbol.to_string(), None);
+  }
+
+  if let Ok(demangled) = rustc_demangle::try_demangle(symbol) {
+    let full = demangled.to_string();
+    let name = format!("{:#}", demangled);
+    return (name.clone(), (full != name).then_some(full));
+  }
+
+  if symbol.starts_with("_Z") {
+    let demangled = cpp_demangle::Symbol::new(symbol)
+      .ok()
+      .and_then(|symbol| symbol.demangle(&Default::default()).ok());
+    if let Some(name) = demangled {
+      return (name, None);
+    }
+  }
+
+  let name = strip_disambiguators(strip_hash(symbol));
+  let full = (name != symbol).then(|| symbol.to_string());
+  (name, full)
+}
+
+/// Mangled, or demangled with the `::h<hash>` or the `[<hash>]` crate
+/// disambiguators left in
+fn is_symbol(name: &str) -> bool {
+  ["_Z", "__Z", "_R"]
+    .iter()
+    .any(|prefix| name.starts_with(prefix))
+    || strip_hash(name) != name
+    || strip_disambiguators(name) != name
+}
+
+/// Removes the color escape sequences (CSI) from a line
+pub fn strip_ansi(line: &str) -> String {
+  let mut stripped = String::with_capacity(line.len());
+  let mut chars = line.chars();
+
+  while let Some(c) = chars.next() {
+    if c != '\x1b' {
+      stripped.push(c);
+      continue;
+    }
+    if chars.next() != Some('[') {
+      continue;
+    }
+    // Parameters until the final byte
+    for c in chars.by_ref() {
+      if ('@'..='~').contains(&c) {
+        break;
+      }
+    }
+  }
+
+  stripped
+}
+
+#[test]
+fn demangle_test() {
+  let (name, full) =
+    demangle("_ZN4core3ops8function6FnOnce9call_once17h8da7fa642d137fc6E");
+  assert_eq!(name, "core::ops::function::FnOnce::call_once");
+  assert_eq!(
+    full.as_deref(),
+    Some("core::ops::function::FnOnce::call_once::h8da7fa642d137fc6")
+  );
+
+  let (name, _) = demangle("_RNvCs6KMMo9K4nKC_4main4boom");
+  assert_eq!(name, "main::boom");
+
+  let (name, full) = demangle("_ZN3foo3barEi");
+  assert_eq!(name, "foo::bar(int)");
+  assert!(full.is_none());
+
+  let (name, _) = demangle(
+    "<std[e28293b1aa0f68bd]::io::Stdout as core[c1f1a4ba060b9bfa]::ops::Fn<[u8]>>::write",
+  );
+  assert_eq!(name, "<std::io::Stdout as core::ops::Fn<[u8]>>::write");
+
+  assert_eq!(demangle("main"), ("main".to_string(), None));
+  assert_eq!(demangle("??"), ("??".to_string(), None));
+  let span = "myapp::handler with id=[5]";
+  assert_eq!(demangle(span), (span.to_string(), None));
+}
+
+#[test]
+fn encode_path_test() {
+  assert_eq!(
+    encode_path("/home/me/my café.rs"),
+    "/home/me/my%20caf%C3%A9.rs"
+  );
+}
+
+#[test]
+fn private_dir_test() {
+  use std::os::unix::fs::PermissionsExt;
+
+  let dir =
+    env::temp_dir().join(format!("stacky-private-{}", std::process::id()));
+  // SAFETY: getuid can't fail
+  let uid = unsafe { libc::getuid() };
+  private_dir(&dir, uid).unwrap();
+  assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+  assert!(private_dir(&dir, uid + 1).is_err());
+
+  // Others could plant a socket in it
+  fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+  assert!(private_dir(&dir, uid).is_err());
+
+  fs::remove_dir(dir).unwrap();
+}
+
+#[test]
+fn format_time_test() {
+  assert_eq!(format_time(0), "1970-01-01 00:00:00");
+  assert_eq!(format_time(1709298309), "2024-03-01 13:05:09");
 }